@@ -0,0 +1,125 @@
+//! Checks of the image used by script assertions
+
+use crate::{term, Image, Rect};
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+impl Image {
+    /// Returns the FNV-1a hash of the size and pixels of the image
+    pub fn hash(&self) -> u64 {
+        let mut bytes: Vec<u8> = Vec::new();
+        bytes.extend((self.get_width() as u32).to_le_bytes());
+        bytes.extend((self.get_height() as u32).to_le_bytes());
+        bytes.extend(self.grid.iter().flatten().map(|&pixel| pixel as u8));
+        bytes.iter().fold(FNV_OFFSET_BASIS, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+        })
+    }
+    /// Returns the number of pixels of `color`
+    pub fn count(&self, color: bool) -> usize {
+        self.grid
+            .iter()
+            .flatten()
+            .filter(|&&pixel| pixel == color)
+            .count()
+    }
+    /// Returns the smallest rectangle holding the pixels of `color`, if there are any
+    pub fn bounds(&self, color: bool) -> Option<Rect> {
+        let mut bounds: Option<(usize, usize, usize, usize)> = None;
+        for (y, line) in self.grid.iter().enumerate() {
+            for (x, &pixel) in line.iter().enumerate() {
+                if pixel == color {
+                    let (x1, y1, x2, y2) = bounds.unwrap_or((x, y, x, y));
+                    bounds = Some((x1.min(x), y1.min(y), x2.max(x), y2.max(y)));
+                }
+            }
+        }
+        bounds.map(|(x1, y1, x2, y2)| Rect {
+            x: x1 as i32,
+            y: y1 as i32,
+            w: (x2 - x1 + 1) as i32,
+            h: (y2 - y1 + 1) as i32,
+        })
+    }
+    /// Draws the pixels within 3 pixels of (x, y), which is shown as differing from `expected`
+    pub fn neighbourhood(&self, x: i32, y: i32, expected: bool) -> String {
+        let area = Rect {
+            x: x - 3,
+            y: y - 3,
+            w: 7,
+            h: 7,
+        }
+        .intersection(&Rect {
+            x: 0,
+            y: 0,
+            w: self.get_width() as i32,
+            h: self.get_height() as i32,
+        });
+        let window: Vec<Vec<bool>> = self.grid[area.y as usize..(area.y + area.h) as usize]
+            .iter()
+            .map(|line| line[area.x as usize..(area.x + area.w) as usize].to_vec())
+            .collect();
+        let mut expected_window = window.clone();
+        expected_window[(y - area.y) as usize][(x - area.x) as usize] = expected;
+        let (drawing, _) = draw_differences(&window, &expected_window);
+        format!(
+            "pixels from ({}, {}) to ({}, {}) ({}: extra `t`, {}: missing `t`)\n{drawing}",
+            area.x,
+            area.y,
+            area.x + area.w - 1,
+            area.y + area.h - 1,
+            term::error("▓▓"),
+            term::error("░░")
+        )
+    }
+    /// Describes the pixels differing from `expected`, or returns `None` if there are none
+    ///
+    /// Extra `t` pixels are drawn in red and missing ones are shaded in red.
+    pub fn diff(&self, expected: &[Vec<bool>]) -> Option<String> {
+        let expected_width = expected.first().map_or(0, Vec::len);
+        if (expected_width, expected.len()) != (self.get_width(), self.get_height()) {
+            return Some(format!(
+                "expected a {}x{} image, not {}x{}",
+                expected_width,
+                expected.len(),
+                self.get_width(),
+                self.get_height()
+            ));
+        }
+        let (drawing, mismatches) = draw_differences(&self.grid, expected);
+        if mismatches == 0 {
+            return None;
+        }
+        Some(format!(
+            "{mismatches} pixels differ ({}: extra `t`, {}: missing `t`)\n{drawing}",
+            term::error("▓▓"),
+            term::error("░░")
+        ))
+    }
+}
+
+/// Draws `actual` in a frame with the pixels differing from `expected` in red, then returns the
+/// drawing and the number of differing pixels
+fn draw_differences(actual: &[Vec<bool>], expected: &[Vec<bool>]) -> (String, usize) {
+    let width = actual.first().map_or(0, Vec::len);
+    let mut mismatches = 0;
+    let mut drawing = format!("+{}+\n", "-".repeat(2 * width));
+    for (line, expected_line) in actual.iter().zip(expected) {
+        drawing += "|";
+        for (&pixel, &expected_pixel) in line.iter().zip(expected_line) {
+            drawing += &match (pixel, expected_pixel) {
+                (true, true) => "██".to_owned(),
+                (false, false) => "  ".to_owned(),
+                (true, false) => term::error("▓▓"),
+                (false, true) => term::error("░░"),
+            };
+            if pixel != expected_pixel {
+                mismatches += 1;
+            }
+        }
+        drawing += "|\n";
+    }
+    drawing += &format!("+{}+", "-".repeat(2 * width));
+    (drawing, mismatches)
+}
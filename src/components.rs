@@ -0,0 +1,164 @@
+//! Connected-component labeling and region statistics
+
+use crate::{Connectivity, Image, Rect};
+
+/// A connected region of pixels of the same color
+#[derive(Clone, PartialEq, Debug)]
+pub struct Component {
+    pub pixels: Vec<(i32, i32)>,
+    pub bounding_box: Rect,
+    pub centroid: (f32, f32),
+}
+
+impl Component {
+    fn new(pixels: Vec<(i32, i32)>) -> Component {
+        let min_x = pixels.iter().map(|&(x, _)| x).min().unwrap();
+        let max_x = pixels.iter().map(|&(x, _)| x).max().unwrap();
+        let min_y = pixels.iter().map(|&(_, y)| y).min().unwrap();
+        let max_y = pixels.iter().map(|&(_, y)| y).max().unwrap();
+        let sum_x: i64 = pixels.iter().map(|&(x, _)| x as i64).sum();
+        let sum_y: i64 = pixels.iter().map(|&(_, y)| y as i64).sum();
+        let area = pixels.len() as f32;
+        Component {
+            bounding_box: Rect {
+                x: min_x,
+                y: min_y,
+                w: max_x - min_x + 1,
+                h: max_y - min_y + 1,
+            },
+            centroid: (sum_x as f32 / area, sum_y as f32 / area),
+            pixels,
+        }
+    }
+    pub fn area(&self) -> usize {
+        self.pixels.len()
+    }
+}
+
+impl Image {
+    /// Returns the connected regions of `color`, in scanning order
+    pub fn label_components(&self, color: bool, connectivity: Connectivity) -> Vec<Component> {
+        let mut visited = vec![vec![false; self.get_width()]; self.get_height()];
+        let mut components: Vec<Component> = Vec::new();
+        for y in 0..self.get_height() {
+            for x in 0..self.get_width() {
+                if visited[y][x] || self.grid[y][x] != color {
+                    continue;
+                }
+                let pixels =
+                    self.flood_region_unvisited(x as i32, y as i32, connectivity, &mut visited);
+                components.push(Component::new(pixels));
+            }
+        }
+        components
+    }
+    /// Fills the regions of `color` smaller than `minimum_area` with the opposite color
+    ///
+    /// Returns the number of removed regions.
+    pub fn despeckle(
+        &mut self,
+        color: bool,
+        connectivity: Connectivity,
+        minimum_area: usize,
+    ) -> usize {
+        let mut removed = 0;
+        for component in self.label_components(color, connectivity) {
+            if component.area() >= minimum_area {
+                continue;
+            }
+            for (x, y) in component.pixels {
+                self.write_pixel(x, y, !color);
+            }
+            removed += 1;
+        }
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Connectivity, Image, Rect};
+
+    const ROWS: [&str; 4] = ["##..#", "##...", "..#..", "....#"];
+
+    #[test]
+    fn connectivity() {
+        let image = Image::from_rows(&ROWS);
+        let four = image.label_components(true, Connectivity::Four);
+        let areas: Vec<usize> = four.iter().map(|component| component.area()).collect();
+        assert_eq!(areas, [4, 1, 1, 1]);
+        assert_eq!(
+            four[0].bounding_box,
+            Rect {
+                x: 0,
+                y: 0,
+                w: 2,
+                h: 2
+            }
+        );
+        assert_eq!(four[0].centroid, (0.5, 0.5));
+        assert_eq!(four[2].pixels, [(2, 2)]);
+        // The diagonal neighbour joins the square
+        let eight = image.label_components(true, Connectivity::Eight);
+        let areas: Vec<usize> = eight.iter().map(|component| component.area()).collect();
+        assert_eq!(areas, [5, 1, 1]);
+        assert_eq!(
+            eight[0].bounding_box,
+            Rect {
+                x: 0,
+                y: 0,
+                w: 3,
+                h: 3
+            }
+        );
+        assert_eq!(eight[1].pixels, [(4, 0)]);
+        assert_eq!(eight[2].pixels, [(4, 3)]);
+        // The `f` pixels form a single region around the others
+        assert_eq!(image.label_components(false, Connectivity::Four).len(), 1);
+        assert!(Image::new(3, 3, false)
+            .label_components(true, Connectivity::Eight)
+            .is_empty());
+    }
+
+    #[test]
+    fn despeckle_removes_the_regions_below_the_minimum_area() {
+        for (connectivity, minimum_area, removed, expected) in [
+            (
+                Connectivity::Four,
+                2,
+                3,
+                ["##...", "##...", ".....", "....."],
+            ),
+            (
+                Connectivity::Four,
+                4,
+                3,
+                ["##...", "##...", ".....", "....."],
+            ),
+            (Connectivity::Four, 5, 4, ["....."; 4]),
+            (
+                Connectivity::Eight,
+                2,
+                2,
+                ["##...", "##...", "..#..", "....."],
+            ),
+            (Connectivity::Eight, 1, 0, ROWS),
+        ] {
+            let mut image = Image::from_rows(&ROWS);
+            assert_eq!(
+                image.despeckle(true, connectivity, minimum_area),
+                removed,
+                "{connectivity:?}, {minimum_area}"
+            );
+            assert_eq!(
+                image.grid,
+                Image::from_rows(&expected).grid,
+                "{connectivity:?}, {minimum_area}"
+            );
+        }
+        // Holes are filled by despeckling `f`
+        let mut image = Image::from_rows(&["###", "#.#", "###"]);
+        assert_eq!(image.despeckle(false, Connectivity::Four, 2), 1);
+        assert_eq!(image.count(false), 0);
+    }
+}
@@ -0,0 +1,577 @@
+//! User configuration, read from a subset of TOML
+//!
+//! The file holds `key = value` lines under `[table]` headers, values being strings, integers,
+//! booleans or arrays of them.
+
+use crate::render::RenderMode;
+use crate::script::KEYWORDS;
+use crate::theme::{Color, FrameStyle, Theme};
+use crate::{COMMANDS, COMMAND_ALIASES};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+/// Largest width and height of the initial image
+pub const MAX_CANVAS_SIZE: i64 = 10_000;
+
+/// A TOML value
+#[derive(Clone, PartialEq, Debug)]
+enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "a string",
+            Value::Integer(_) => "an integer",
+            Value::Boolean(_) => "a boolean",
+            Value::Array(_) => "an array",
+        }
+    }
+}
+
+/// Parses a TOML document into its values, by dotted key
+fn parse(text: &str) -> Result<HashMap<String, Value>, String> {
+    let mut values: HashMap<String, Value> = HashMap::new();
+    let mut table: Vec<String> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let error = |message: String| format!("line {}: {message}", i + 1);
+        let line = line.trim_start();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('[') {
+            if header.starts_with('[') {
+                return Err(error("arrays of tables aren't supported".to_owned()));
+            }
+            let (key, rest) = parse_key(header).map_err(error)?;
+            let Some(rest) = rest.strip_prefix(']') else {
+                return Err(error("expected ']'".to_owned()));
+            };
+            expect_end(rest).map_err(error)?;
+            table = key;
+            continue;
+        }
+        let (key, rest) = parse_key(line).map_err(error)?;
+        let Some(rest) = rest.strip_prefix('=') else {
+            return Err(error("expected '='".to_owned()));
+        };
+        let (value, rest) = parse_value(rest.trim_start()).map_err(error)?;
+        expect_end(rest).map_err(error)?;
+        let name = table
+            .iter()
+            .chain(&key)
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(".");
+        if values.insert(name.to_owned(), value).is_some() {
+            return Err(error(format!("'{name}' is defined twice")));
+        }
+    }
+    Ok(values)
+}
+
+/// Checks that only spaces and a comment follow a value
+fn expect_end(text: &str) -> Result<(), String> {
+    let text = text.trim_start();
+    if text.is_empty() || text.starts_with('#') {
+        Ok(())
+    } else {
+        Err(format!("unexpected '{}'", text.trim_end()))
+    }
+}
+
+/// Parses a dotted key made of bare and quoted parts, returning the rest of the text
+fn parse_key(text: &str) -> Result<(Vec<String>, &str), String> {
+    let mut parts: Vec<String> = Vec::new();
+    let mut text = text.trim_start();
+    loop {
+        if text.starts_with(['"', '\'']) {
+            let (part, rest) = parse_string(text)?;
+            parts.push(part);
+            text = rest;
+        } else {
+            let end = text
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+                .unwrap_or(text.len());
+            if end == 0 {
+                return Err("expected a key".to_owned());
+            }
+            parts.push(text[..end].to_owned());
+            text = &text[end..];
+        }
+        text = text.trim_start();
+        match text.strip_prefix('.') {
+            Some(rest) => text = rest.trim_start(),
+            None => return Ok((parts, text)),
+        }
+    }
+}
+
+/// Parses a basic (`"`) or literal (`'`) string, returning the rest of the text
+fn parse_string(text: &str) -> Result<(String, &str), String> {
+    let mut characters = text.char_indices();
+    let quote = characters.next().map(|(_, quote)| quote);
+    let mut string = String::new();
+    while let Some((i, character)) = characters.next() {
+        match character {
+            _ if Some(character) == quote => return Ok((string, &text[i + 1..])),
+            '\\' if quote == Some('"') => {
+                let escaped = match characters.next().map(|(_, escaped)| escaped) {
+                    Some('b') => '\u{8}',
+                    Some('t') => '\t',
+                    Some('n') => '\n',
+                    Some('f') => '\u{c}',
+                    Some('r') => '\r',
+                    Some('"') => '"',
+                    Some('\\') => '\\',
+                    Some(unicode @ ('u' | 'U')) => {
+                        let length = if unicode == 'u' { 4 } else { 8 };
+                        let digits: String = characters
+                            .by_ref()
+                            .take(length)
+                            .map(|(_, digit)| digit)
+                            .collect();
+                        u32::from_str_radix(&digits, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or(format!("invalid escape '\\{unicode}{digits}'"))?
+                    }
+                    Some(other) => return Err(format!("invalid escape '\\{other}'")),
+                    None => break,
+                };
+                string.push(escaped);
+            }
+            _ => string.push(character),
+        }
+    }
+    Err("unterminated string".to_owned())
+}
+
+/// Parses a value, returning the rest of the text
+fn parse_value(text: &str) -> Result<(Value, &str), String> {
+    if text.starts_with(['"', '\'']) {
+        if text.starts_with("\"\"\"") || text.starts_with("'''") {
+            return Err("multi-line strings aren't supported".to_owned());
+        }
+        let (string, rest) = parse_string(text)?;
+        return Ok((Value::String(string), rest));
+    }
+    if let Some(mut rest) = text.strip_prefix('[') {
+        let mut array: Vec<Value> = Vec::new();
+        loop {
+            rest = rest.trim_start();
+            if let Some(rest) = rest.strip_prefix(']') {
+                return Ok((Value::Array(array), rest));
+            }
+            let (value, after_value) = parse_value(rest)?;
+            array.push(value);
+            rest = after_value.trim_start();
+            if let Some(after_comma) = rest.strip_prefix(',') {
+                rest = after_comma;
+            } else if !rest.starts_with(']') {
+                return Err("expected ',' or ']' in array".to_owned());
+            }
+        }
+    }
+    let end = text
+        .find(|c: char| c.is_whitespace() || c == ',' || c == ']' || c == '#')
+        .unwrap_or(text.len());
+    let (word, rest) = text.split_at(end);
+    let value = match word {
+        "true" => Value::Boolean(true),
+        "false" => Value::Boolean(false),
+        _ => match word.replace('_', "").parse() {
+            Ok(integer) => Value::Integer(integer),
+            Err(_) if word.is_empty() => return Err("expected a value".to_owned()),
+            Err(_) => return Err(format!("unsupported value '{word}'")),
+        },
+    };
+    Ok((value, rest))
+}
+
+/// Settings read at startup
+#[derive(Clone, PartialEq, Debug)]
+pub struct Config {
+    /// File the settings were read from, if any
+    pub path: Option<PathBuf>,
+    /// Width, height and color of the initial image
+    pub canvas: (i32, i32, bool),
+    pub prompt: String,
+    pub render_mode: RenderMode,
+    pub theme: Theme,
+    /// Number of lines kept in the history file
+    pub history_size: usize,
+    /// Commands run in place of the first word of a command, by name
+    pub aliases: BTreeMap<String, String>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            path: None,
+            canvas: (10, 10, false),
+            prompt: "ipcli> ".to_owned(),
+            render_mode: RenderMode::Blocks,
+            theme: Theme::default(),
+            history_size: 1000,
+            aliases: BTreeMap::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Returns `ipcli/config.toml` in `XDG_CONFIG_HOME`, or else in `~/.config`
+    pub fn default_path() -> Option<PathBuf> {
+        let directory = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(directory) if !directory.is_empty() => PathBuf::from(directory),
+            _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+        };
+        Some(directory.join("ipcli").join("config.toml"))
+    }
+    /// Reads the settings of the file at `path`, the others keeping their default value
+    pub fn load(path: &Path) -> Result<Config, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read '{}': {e}", path.display()))?;
+        let values = parse(&text).map_err(|message| format!("{}: {message}", path.display()))?;
+        let mut config = Config::from_values(values)
+            .map_err(|message| format!("{}: {message}", path.display()))?;
+        config.path = Some(path.to_owned());
+        Ok(config)
+    }
+    fn from_values(mut values: HashMap<String, Value>) -> Result<Config, String> {
+        let mut config = Config::default();
+        // The preset is applied first, whatever its place, for the other keys to change it
+        if let Some(value) = values.remove("theme.preset") {
+            let name = string(&value, "theme.preset")?;
+            config.theme =
+                Theme::from_preset(&name).ok_or(format!("unknown theme preset '{name}'"))?;
+        }
+        let mut keys: Vec<String> = values.keys().cloned().collect();
+        keys.sort();
+        for key in keys {
+            let value = &values[&key];
+            match key.as_str() {
+                "prompt" => config.prompt = string(value, &key)?,
+                "history_size" => config.history_size = integer(value, &key, 0, i64::MAX)? as usize,
+                "canvas.width" => {
+                    config.canvas.0 = integer(value, &key, 1, MAX_CANVAS_SIZE)? as i32
+                }
+                "canvas.height" => {
+                    config.canvas.1 = integer(value, &key, 1, MAX_CANVAS_SIZE)? as i32
+                }
+                "canvas.color" => match value {
+                    Value::Boolean(color) => config.canvas.2 = *color,
+                    _ => return Err(wrong_type(value, &key, "a boolean")),
+                },
+                "view.mode" => {
+                    let name = string(value, &key)?;
+                    config.render_mode = RenderMode::from_name(&name)
+                        .ok_or(format!("unknown render mode '{name}'"))?;
+                }
+                "theme.glyphs" => {
+                    let glyphs = match value {
+                        Value::Array(glyphs) => glyphs
+                            .iter()
+                            .map(|glyph| string(glyph, &key))
+                            .collect::<Result<Vec<String>, String>>()?,
+                        _ => return Err(wrong_type(value, &key, "an array")),
+                    };
+                    let characters: Vec<char> =
+                        glyphs.iter().flat_map(|glyph| glyph.chars()).collect();
+                    match (glyphs.len(), characters.as_slice()) {
+                        (2, &[on, off]) => (config.theme.on, config.theme.off) = (on, off),
+                        _ => return Err(format!("'{key}' must be two single characters")),
+                    }
+                }
+                "theme.foreground" => config.theme.foreground = color(value, &key)?,
+                "theme.background" => config.theme.background = color(value, &key)?,
+                "theme.frame" => {
+                    let name = string(value, &key)?;
+                    config.theme.frame = FrameStyle::from_name(&name)
+                        .ok_or(format!("unknown frame style '{name}'"))?;
+                }
+                _ => match key.strip_prefix("aliases.") {
+                    Some(name) => {
+                        let command = string(value, &key)?;
+                        let is_taken = COMMANDS.contains(&name)
+                            || COMMAND_ALIASES.iter().any(|(_, alias)| *alias == name)
+                            || KEYWORDS.contains(&name);
+                        if is_taken
+                            || name.is_empty()
+                            || name
+                                .chars()
+                                .any(|c| !c.is_ascii_lowercase() && !c.is_ascii_digit() && c != '_')
+                        {
+                            return Err(format!("'{name}' can't be an alias"));
+                        }
+                        if command.trim().is_empty() {
+                            return Err(format!("the alias '{name}' is empty"));
+                        }
+                        config.aliases.insert(name.to_owned(), command);
+                    }
+                    None => return Err(format!("unknown key '{key}'")),
+                },
+            }
+        }
+        Ok(config)
+    }
+    /// Returns the settings as a configuration file
+    pub fn to_toml(&self) -> String {
+        let color =
+            |color: Option<Color>| quoted(&color.map_or("none".to_owned(), |color| color.name()));
+        let mut toml = match &self.path {
+            Some(path) => format!("# {}\n", path.display()),
+            None => "# default settings, no configuration file was read\n".to_owned(),
+        };
+        toml += &format!("prompt = {}\n", quoted(&self.prompt));
+        toml += &format!("history_size = {}\n", self.history_size);
+        let (width, height, canvas_color) = self.canvas;
+        toml +=
+            &format!("\n[canvas]\nwidth = {width}\nheight = {height}\ncolor = {canvas_color}\n");
+        toml += &format!("\n[view]\nmode = {}\n", quoted(self.render_mode.name()));
+        toml += &format!(
+            "\n[theme]\nglyphs = [{}, {}]\nforeground = {}\nbackground = {}\nframe = {}\n",
+            quoted(&self.theme.on.to_string()),
+            quoted(&self.theme.off.to_string()),
+            color(self.theme.foreground),
+            color(self.theme.background),
+            quoted(self.theme.frame.name())
+        );
+        toml += "\n[aliases]\n";
+        for (name, command) in &self.aliases {
+            toml += &format!("{name} = {}\n", quoted(command));
+        }
+        toml
+    }
+}
+
+fn wrong_type(value: &Value, key: &str, expected: &str) -> String {
+    format!("'{key}' must be {expected}, not {}", value.type_name())
+}
+
+fn string(value: &Value, key: &str) -> Result<String, String> {
+    match value {
+        Value::String(string) => Ok(string.to_owned()),
+        _ => Err(wrong_type(value, key, "a string")),
+    }
+}
+
+/// Reads an integer from `min` to `max`
+fn integer(value: &Value, key: &str, min: i64, max: i64) -> Result<i64, String> {
+    match value {
+        Value::Integer(integer) if *integer < min => {
+            Err(format!("'{key}' can't be smaller than {min}"))
+        }
+        Value::Integer(integer) if *integer > max => {
+            Err(format!("'{key}' can't be larger than {max}"))
+        }
+        Value::Integer(integer) => Ok(*integer),
+        _ => Err(wrong_type(value, key, "an integer")),
+    }
+}
+
+/// Returns `text` as a basic string
+fn quoted(text: &str) -> String {
+    let mut string = String::from('"');
+    for character in text.chars() {
+        match character {
+            '"' => string += "\\\"",
+            '\\' => string += "\\\\",
+            '\n' => string += "\\n",
+            '\t' => string += "\\t",
+            _ if character.is_control() => string += &format!("\\u{:04x}", character as u32),
+            _ => string.push(character),
+        }
+    }
+    string.push('"');
+    string
+}
+
+/// Reads a color, or `none`
+fn color(value: &Value, key: &str) -> Result<Option<Color>, String> {
+    let name = match value {
+        Value::Integer(number) => number.to_string(),
+        _ => string(value, key)?,
+    };
+    if name == "none" {
+        return Ok(None);
+    }
+    Color::from_name(&name)
+        .map(Some)
+        .ok_or(format!("unknown color '{name}'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Config};
+    use crate::render::RenderMode;
+    use crate::theme::{Color, FrameStyle};
+
+    fn config(text: &str) -> Result<Config, String> {
+        Config::from_values(parse(text)?)
+    }
+
+    #[test]
+    fn valid_file() {
+        let config = config(
+            r#"
+# Comments and blank lines are skipped
+prompt = "> "  # after values too
+history_size = 1_000
+
+[canvas]
+width = 32
+height = 16
+color = true
+
+[view]
+mode = "braille"
+
+[theme]
+frame = "double"
+preset = "amber"
+glyphs = ['#', "."]
+background = 17
+
+[aliases]
+sq = "draw_rectangle 0 0"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.prompt, "> ");
+        assert_eq!(config.history_size, 1000);
+        assert_eq!(config.canvas, (32, 16, true));
+        assert_eq!(config.render_mode, RenderMode::Braille);
+        // The preset comes first, whatever its place
+        assert_eq!(config.theme.frame, FrameStyle::Double);
+        assert_eq!(config.theme.foreground, Some(Color::Rgb(0xff, 0xb0, 0)));
+        assert_eq!(config.theme.background, Some(Color::Indexed(17)));
+        assert_eq!((config.theme.on, config.theme.off), ('#', '.'));
+        assert_eq!(config.aliases["sq"], "draw_rectangle 0 0");
+    }
+
+    #[test]
+    fn empty_file_keeps_the_defaults() {
+        assert_eq!(config(""), Ok(Config::default()));
+    }
+
+    #[test]
+    fn syntax_errors() {
+        for (text, message) in [
+            ("[[aliases]]", "line 1: arrays of tables aren't supported"),
+            ("[canvas", "line 1: expected ']'"),
+            ("[canvas] width", "line 1: unexpected 'width'"),
+            ("\nprompt \"> \"", "line 2: expected '='"),
+            (
+                "prompt = \"a\"\nprompt = \"b\"",
+                "line 2: 'prompt' is defined twice",
+            ),
+            ("= 1", "line 1: expected a key"),
+            ("prompt = \"\\q\"", "line 1: invalid escape '\\q'"),
+            ("prompt = \"\\ud800\"", "line 1: invalid escape '\\ud800'"),
+            ("prompt = \"> ", "line 1: unterminated string"),
+            (
+                "prompt = \"\"\"> \"\"\"",
+                "line 1: multi-line strings aren't supported",
+            ),
+            (
+                "glyphs = [\"a\" \"b\"]",
+                "line 1: expected ',' or ']' in array",
+            ),
+            ("prompt =", "line 1: expected a value"),
+            ("history_size = 1.5", "line 1: unsupported value '1.5'"),
+            ("prompt = \"> \" \"\"", "line 1: unexpected '\"\"'"),
+        ] {
+            assert_eq!(parse(text).err().as_deref(), Some(message), "{text}");
+        }
+    }
+
+    #[test]
+    fn setting_errors() {
+        for (text, message) in [
+            ("[theme]\npreset = \"neon\"", "unknown theme preset 'neon'"),
+            ("prompt = 1", "'prompt' must be a string, not an integer"),
+            (
+                "history_size = -1",
+                "'history_size' can't be smaller than 0",
+            ),
+            (
+                "[canvas]\nwidth = 0",
+                "'canvas.width' can't be smaller than 1",
+            ),
+            (
+                "[canvas]\nheight = 100000",
+                "'canvas.height' can't be larger than 10000",
+            ),
+            (
+                "[canvas]\ncolor = \"t\"",
+                "'canvas.color' must be a boolean, not a string",
+            ),
+            ("[view]\nmode = \"ascii\"", "unknown render mode 'ascii'"),
+            (
+                "[theme]\nglyphs = \"#.\"",
+                "'theme.glyphs' must be an array, not a string",
+            ),
+            (
+                "[theme]\nglyphs = [\"##\", \".\"]",
+                "'theme.glyphs' must be two single characters",
+            ),
+            (
+                "[theme]\nglyphs = [1, 2]",
+                "'theme.glyphs' must be a string, not an integer",
+            ),
+            (
+                "[theme]\nforeground = \"000080x\"",
+                "unknown color '000080x'",
+            ),
+            (
+                "[theme]\nframe = \"dotted\"",
+                "unknown frame style 'dotted'",
+            ),
+            ("[aliases]\ndr = \"draw_line\"", "'dr' can't be an alias"),
+            ("[aliases]\nSq = \"draw_line\"", "'Sq' can't be an alias"),
+            (
+                "[aliases]\nrepeat = \"draw_line\"",
+                "'repeat' can't be an alias",
+            ),
+            ("[aliases]\nif = \"draw_line\"", "'if' can't be an alias"),
+            ("[aliases]\ndef = \"draw_line\"", "'def' can't be an alias"),
+            (
+                "[aliases]\nexport = \"draw_line\"",
+                "'export' can't be an alias",
+            ),
+            ("[aliases]\nsq = \" \"", "the alias 'sq' is empty"),
+            ("[canvas]\ndepth = 1", "unknown key 'canvas.depth'"),
+        ] {
+            assert_eq!(config(text).err().as_deref(), Some(message), "{text}");
+        }
+    }
+
+    #[test]
+    fn to_toml_reads_back() {
+        let mut settings = Config {
+            prompt: "\u{1b}[1m\"ipcli\"\\\t> \u{1b}[0m".to_owned(),
+            history_size: 10,
+            canvas: (640, 480, true),
+            render_mode: RenderMode::Half,
+            ..Config::default()
+        };
+        settings.theme.on = '"';
+        settings.theme.off = ' ';
+        settings.theme.foreground = Some(Color::Rgb(0, 0, 0x80));
+        settings.theme.background = Some(Color::Basic(3));
+        settings.theme.frame = FrameStyle::Heavy;
+        settings
+            .aliases
+            .insert("sq".to_owned(), "dr 0 0".to_owned());
+        assert_eq!(config(&settings.to_toml()), Ok(settings.clone()));
+        // The path is only a comment
+        settings.path = Some("config.toml".into());
+        assert_eq!(config(&settings.to_toml()).unwrap().path, None);
+        assert_eq!(config(&Config::default().to_toml()), Ok(Config::default()));
+    }
+}
@@ -0,0 +1,188 @@
+//! Outline extraction and contour tracing
+
+use crate::{Connectivity, Image};
+
+/// Clockwise neighbour directions, starting from the left
+const DIRECTIONS: [(i32, i32); 8] = [
+    (-1, 0),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Boundary {
+    /// Pixels of the region touching the outside
+    Inner,
+    /// Pixels outside the region touching it
+    Outer,
+}
+
+impl Boundary {
+    pub fn from_name(name: &str) -> Option<Boundary> {
+        match name {
+            "inner" => Some(Boundary::Inner),
+            "outer" => Some(Boundary::Outer),
+            _ => None,
+        }
+    }
+}
+
+impl Image {
+    /// Whether (x, y) is of `color`, pixels outside the image being of neither color
+    fn is_color(&self, x: i32, y: i32, color: bool) -> bool {
+        x >= 0
+            && y >= 0
+            && x < self.get_width() as i32
+            && y < self.get_height() as i32
+            && self.grid[y as usize][x as usize] == color
+    }
+    /// Whether any neighbour of (x, y) isn't of `color`
+    fn touches_other_color(&self, x: i32, y: i32, color: bool, connectivity: Connectivity) -> bool {
+        connectivity
+            .offsets()
+            .iter()
+            .any(|&(dx, dy)| !self.is_color(x + dx, y + dy, color))
+    }
+    /// Whether any neighbour of (x, y) is of `color`
+    fn touches_color(&self, x: i32, y: i32, color: bool, connectivity: Connectivity) -> bool {
+        connectivity
+            .offsets()
+            .iter()
+            .any(|&(dx, dy)| self.is_color(x + dx, y + dy, color))
+    }
+    /// Replaces the regions of `color` with their 1-pixel boundary
+    ///
+    /// `connectivity` is the neighbourhood used to find boundary pixels.
+    pub fn outline(&mut self, color: bool, boundary: Boundary, connectivity: Connectivity) {
+        let is_boundary: Vec<Vec<bool>> = (0..self.get_height() as i32)
+            .map(|y| {
+                (0..self.get_width() as i32)
+                    .map(|x| match boundary {
+                        Boundary::Inner => {
+                            self.is_color(x, y, color)
+                                && self.touches_other_color(x, y, color, connectivity)
+                        }
+                        Boundary::Outer => {
+                            self.is_color(x, y, !color)
+                                && self.touches_color(x, y, color, connectivity)
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        self.grid = is_boundary
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|&pixel| if pixel { color } else { !color })
+                    .collect()
+            })
+            .collect();
+    }
+    /// Traces the outer boundary of every 8-connected region of `color`
+    ///
+    /// Each contour is the ordered list of the boundary pixels of a region, going clockwise from
+    /// its top-left pixel, found with Moore-neighbour tracing.
+    pub fn trace_contours(&self, color: bool) -> Vec<Vec<(i32, i32)>> {
+        self.label_components(color, Connectivity::Eight)
+            .iter()
+            .map(|component| {
+                // Components are labeled in scanning order, so their first pixel is top-left
+                self.trace_contour(component.pixels[0], color)
+            })
+            .collect()
+    }
+    fn trace_contour(&self, start: (i32, i32), color: bool) -> Vec<(i32, i32)> {
+        let mut contour = vec![start];
+        let mut current = start;
+        // The pixel left of the top-left pixel is outside the region
+        let mut backtrack = 0;
+        loop {
+            let found = (1..=8).map(|i| (backtrack + i) % 8).find(|&direction| {
+                let (dx, dy) = DIRECTIONS[direction];
+                self.is_color(current.0 + dx, current.1 + dy, color)
+            });
+            let Some(direction) = found else {
+                // Isolated pixel
+                break;
+            };
+            let (dx, dy) = DIRECTIONS[direction];
+            let next = (current.0 + dx, current.1 + dy);
+            if current == start && contour.len() > 1 && next == contour[1] {
+                // Back to the start, about to go around again
+                contour.pop();
+                break;
+            }
+            // The neighbour scanned before `next` is outside the region
+            let (previous_dx, previous_dy) = DIRECTIONS[(direction + 7) % 8];
+            let outside = (previous_dx - dx, previous_dy - dy);
+            backtrack = DIRECTIONS.iter().position(|&d| d == outside).unwrap();
+            current = next;
+            contour.push(current);
+        }
+        contour
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Image;
+
+    fn contours(rows: &[&str]) -> Vec<Vec<(i32, i32)>> {
+        Image::from_rows(rows).trace_contours(true)
+    }
+
+    #[test]
+    fn single_pixels() {
+        assert_eq!(contours(&["...", ".#.", "..."]), [[(1, 1)]]);
+        assert_eq!(contours(&["#..", "...", "..#"]), [[(0, 0)], [(2, 2)]]);
+        assert!(contours(&["...", "..."]).is_empty());
+    }
+
+    #[test]
+    fn shape_with_a_hole() {
+        // Only the outer boundary is traced, clockwise from the top-left pixel
+        assert_eq!(
+            contours(&[".....", ".###.", ".#.#.", ".###.", "....."]),
+            [[
+                (1, 1),
+                (2, 1),
+                (3, 1),
+                (3, 2),
+                (3, 3),
+                (2, 3),
+                (1, 3),
+                (1, 2)
+            ]]
+        );
+        assert_eq!(
+            contours(&["######", "#....#", "#.##.#", "#....#", "######"])
+                .iter()
+                .map(Vec::len)
+                .collect::<Vec<usize>>(),
+            [18, 2]
+        );
+    }
+
+    #[test]
+    fn shapes_touching_the_edges() {
+        assert_eq!(
+            contours(&["###", "###"]),
+            [[(0, 0), (1, 0), (2, 0), (2, 1), (1, 1), (0, 1)]]
+        );
+        assert_eq!(
+            contours(&["##..", "#...", "...#"]),
+            [vec![(0, 0), (1, 0), (0, 1)], vec![(3, 2)]]
+        );
+        // A diagonal line is one 8-connected region, gone through both ways
+        assert_eq!(
+            contours(&["#..", ".#.", "..#"]),
+            [[(0, 0), (1, 1), (2, 2), (1, 1)]]
+        );
+    }
+}
@@ -0,0 +1,259 @@
+//! Conversion of grayscale images to one-bit bitmaps
+
+/// 8×8 ordered dithering matrix
+const BAYER_8X8: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+/// Error diffusion kernels, as (dx, dy, weight) with the sum of the weights
+const FLOYD_STEINBERG: (&[(i32, i32, f32)], f32) =
+    (&[(1, 0, 7.0), (-1, 1, 3.0), (0, 1, 5.0), (1, 1, 1.0)], 16.0);
+// Atkinson only diffuses three quarters of the error
+const ATKINSON: (&[(i32, i32, f32)], f32) = (
+    &[
+        (1, 0, 1.0),
+        (2, 0, 1.0),
+        (-1, 1, 1.0),
+        (0, 1, 1.0),
+        (1, 1, 1.0),
+        (0, 2, 1.0),
+    ],
+    8.0,
+);
+const STUCKI: (&[(i32, i32, f32)], f32) = (
+    &[
+        (1, 0, 8.0),
+        (2, 0, 4.0),
+        (-2, 1, 2.0),
+        (-1, 1, 4.0),
+        (0, 1, 8.0),
+        (1, 1, 4.0),
+        (2, 1, 2.0),
+        (-2, 2, 1.0),
+        (-1, 2, 2.0),
+        (0, 2, 4.0),
+        (1, 2, 2.0),
+        (2, 2, 1.0),
+    ],
+    42.0,
+);
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Algorithm {
+    /// Fixed threshold, from 0 to 255
+    Threshold(u8),
+    /// Threshold chosen by Otsu's method
+    Otsu,
+    Bayer,
+    FloydSteinberg,
+    Atkinson,
+    Stucki,
+}
+
+impl Algorithm {
+    /// Parses an algorithm name followed by its optional argument
+    pub fn from_arguments(arguments: &[&str]) -> Option<Algorithm> {
+        match arguments {
+            [] => Some(Algorithm::FloydSteinberg),
+            ["threshold"] => Some(Algorithm::Threshold(128)),
+            ["threshold", level] => level.parse().ok().map(Algorithm::Threshold),
+            ["otsu"] => Some(Algorithm::Otsu),
+            ["bayer"] => Some(Algorithm::Bayer),
+            ["floyd_steinberg" | "fs"] => Some(Algorithm::FloydSteinberg),
+            ["atkinson"] => Some(Algorithm::Atkinson),
+            ["stucki"] => Some(Algorithm::Stucki),
+            _ => None,
+        }
+    }
+}
+
+/// Converts luminance values (0 is black) to pixels, `true` being black
+///
+/// `serpentine` alternates the scanning direction of error diffusion on every row.
+pub fn dither(luminance: &[Vec<f32>], algorithm: Algorithm, serpentine: bool) -> Vec<Vec<bool>> {
+    match algorithm {
+        Algorithm::Threshold(level) => threshold(luminance, level as f32 / 255.0),
+        Algorithm::Otsu => threshold(luminance, otsu_threshold(luminance)),
+        Algorithm::Bayer => luminance
+            .iter()
+            .enumerate()
+            .map(|(y, row)| {
+                row.iter()
+                    .enumerate()
+                    .map(|(x, &value)| value < (BAYER_8X8[y % 8][x % 8] as f32 + 0.5) / 64.0)
+                    .collect()
+            })
+            .collect(),
+        Algorithm::FloydSteinberg => diffuse_error(luminance, FLOYD_STEINBERG, serpentine),
+        Algorithm::Atkinson => diffuse_error(luminance, ATKINSON, serpentine),
+        Algorithm::Stucki => diffuse_error(luminance, STUCKI, serpentine),
+    }
+}
+
+fn threshold(luminance: &[Vec<f32>], level: f32) -> Vec<Vec<bool>> {
+    luminance
+        .iter()
+        .map(|row| row.iter().map(|&value| value < level).collect())
+        .collect()
+}
+
+/// Finds the threshold maximizing the variance between the dark and light classes
+fn otsu_threshold(luminance: &[Vec<f32>]) -> f32 {
+    let mut histogram = [0usize; 256];
+    for value in luminance.iter().flatten() {
+        histogram[(value.clamp(0.0, 1.0) * 255.0).round() as usize] += 1;
+    }
+    let total: usize = histogram.iter().sum();
+    let weighted_total: f64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(level, &count)| level as f64 * count as f64)
+        .sum();
+    let mut best_level = 0;
+    let mut best_variance = -1.0;
+    let mut dark_count = 0;
+    let mut dark_weighted = 0.0;
+    for (level, &count) in histogram.iter().enumerate() {
+        dark_count += count;
+        dark_weighted += level as f64 * count as f64;
+        let light_count = total - dark_count;
+        if dark_count == 0 || light_count == 0 {
+            continue;
+        }
+        let dark_mean = dark_weighted / dark_count as f64;
+        let light_mean = (weighted_total - dark_weighted) / light_count as f64;
+        let variance = dark_count as f64 * light_count as f64 * (dark_mean - light_mean).powi(2);
+        if variance > best_variance {
+            best_variance = variance;
+            best_level = level;
+        }
+    }
+    // Levels up to and including the best one are dark
+    (best_level as f32 + 0.5) / 255.0
+}
+
+fn diffuse_error(
+    luminance: &[Vec<f32>],
+    kernel: (&[(i32, i32, f32)], f32),
+    serpentine: bool,
+) -> Vec<Vec<bool>> {
+    let (offsets, divisor) = kernel;
+    let height = luminance.len();
+    let width = luminance[0].len();
+    let mut values = luminance.to_vec();
+    let mut pixels = vec![vec![false; width]; height];
+    for y in 0..height {
+        let reversed = serpentine && y % 2 == 1;
+        for i in 0..width {
+            let x = if reversed { width - 1 - i } else { i };
+            let black = values[y][x] < 0.5;
+            pixels[y][x] = black;
+            let error = values[y][x] - if black { 0.0 } else { 1.0 };
+            for &(dx, dy, weight) in offsets {
+                // Mirror the kernel when scanning from right to left
+                let dx = if reversed { -dx } else { dx };
+                let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+                if nx < 0 || nx >= width as i32 || ny >= height as i32 {
+                    continue;
+                }
+                values[ny as usize][nx as usize] += error * weight / divisor;
+            }
+        }
+    }
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{dither, otsu_threshold, Algorithm, ATKINSON, FLOYD_STEINBERG, STUCKI};
+
+    /// A row going from black to white through every level
+    fn gradient() -> Vec<Vec<f32>> {
+        vec![(0..=255).map(|level| level as f32 / 255.0).collect()]
+    }
+
+    fn solid(value: f32) -> Vec<Vec<f32>> {
+        vec![vec![value; 16]; 16]
+    }
+
+    fn count(pixels: &[Vec<bool>]) -> usize {
+        pixels.iter().flatten().filter(|&&pixel| pixel).count()
+    }
+
+    #[test]
+    fn thresholds() {
+        // Otsu splits a uniform histogram in its middle
+        assert_eq!(otsu_threshold(&gradient()), 127.5 / 255.0);
+        for algorithm in [Algorithm::Otsu, Algorithm::Threshold(128)] {
+            let pixels = dither(&gradient(), algorithm, false);
+            assert_eq!(pixels[0][..128], [true; 128], "{algorithm:?}");
+            assert_eq!(pixels[0][128..], [false; 128], "{algorithm:?}");
+        }
+        // Two levels are told apart whatever their share of the image
+        let mut two_levels = vec![vec![0.2; 10]; 10];
+        two_levels[3][4] = 0.8;
+        let threshold = otsu_threshold(&two_levels);
+        assert!(0.2 < threshold && threshold < 0.8, "{threshold}");
+    }
+
+    #[test]
+    fn solid_images_stay_solid() {
+        for algorithm in [
+            Algorithm::Otsu,
+            Algorithm::Threshold(128),
+            Algorithm::Bayer,
+            Algorithm::FloydSteinberg,
+            Algorithm::Atkinson,
+            Algorithm::Stucki,
+        ] {
+            for serpentine in [false, true] {
+                assert_eq!(
+                    count(&dither(&solid(0.0), algorithm, serpentine)),
+                    256,
+                    "{algorithm:?}"
+                );
+                assert_eq!(
+                    count(&dither(&solid(1.0), algorithm, serpentine)),
+                    0,
+                    "{algorithm:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn kernels() {
+        for (kernel, diffused) in [(FLOYD_STEINBERG, 1.0), (ATKINSON, 0.75), (STUCKI, 1.0)] {
+            let (offsets, divisor) = kernel;
+            let sum: f32 = offsets.iter().map(|&(_, _, weight)| weight).sum();
+            assert_eq!(sum / divisor, diffused);
+            // The error only goes to pixels that aren't scanned yet
+            assert!(offsets
+                .iter()
+                .all(|&(dx, dy, _)| dy > 0 || (dy == 0 && dx > 0)));
+        }
+        // Mid-gray becomes a checkerboard
+        let pixels = dither(&vec![vec![0.5; 2]; 2], Algorithm::FloydSteinberg, false);
+        assert_eq!(pixels, [[false, true], [true, false]]);
+    }
+
+    #[test]
+    fn dithering_keeps_the_average_luminance() {
+        // A quarter of the pixels of a 75% white image are black
+        assert_eq!(count(&dither(&solid(0.75), Algorithm::Bayer, false)), 64);
+        for algorithm in [Algorithm::FloydSteinberg, Algorithm::Stucki] {
+            for serpentine in [false, true] {
+                // Or a few less, the error diffused past the edges being lost
+                let black = count(&dither(&solid(0.75), algorithm, serpentine));
+                assert!((56..=64).contains(&black), "{algorithm:?}: {black}");
+            }
+        }
+    }
+}
@@ -0,0 +1,309 @@
+//! Full-screen editor painting with the keyboard and the mouse
+//!
+//! Every change is made by running a `write` command, so that it is logged like typed commands.
+
+use crate::render::RenderMode;
+use crate::{term, Cli, DrawMode};
+use std::io::{IsTerminal, Read, Write};
+
+const HELP_LINE: &str =
+    "arrows: move, space: toggle, t/f: set, mouse: paint (right button: erase), q: quit";
+
+/// An action read from the terminal
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Event {
+    Move(i32, i32),
+    Toggle,
+    Set(bool),
+    /// A mouse button pressed or dragged over a screen position, 1-based
+    Paint {
+        column: usize,
+        row: usize,
+        color: bool,
+    },
+    Quit,
+}
+
+/// Keeps the terminal in raw mode with mouse reporting on the alternate screen until dropped
+struct Screen {
+    _raw_mode: term::RawMode,
+}
+
+impl Screen {
+    fn enter() -> Result<Screen, String> {
+        let raw_mode = term::RawMode::enable()?;
+        // Alternate screen, hidden cursor, then button, drag and SGR mouse reporting
+        print!("\x1b[?1049h\x1b[?25l\x1b[?1000h\x1b[?1002h\x1b[?1006h");
+        std::io::stdout().flush().map_err(|e| e.to_string())?;
+        Ok(Screen {
+            _raw_mode: raw_mode,
+        })
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        print!("\x1b[?1006l\x1b[?1002l\x1b[?1000l\x1b[?25h\x1b[?1049l");
+        let _ = std::io::stdout().flush();
+    }
+}
+
+/// Decodes the keys and mouse reports in `bytes`
+fn parse_events(bytes: &[u8]) -> Vec<Event> {
+    let mut events: Vec<Event> = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i..] {
+            [0x1b, b'[', b'<', ..] => {
+                // SGR mouse report: ESC [ < button ; column ; row (M | m)
+                let end = bytes[i..]
+                    .iter()
+                    .position(|&byte| byte == b'M' || byte == b'm')
+                    .map_or(bytes.len(), |end| i + end);
+                let report = String::from_utf8_lossy(&bytes[i + 3..end]).into_owned();
+                let numbers: Vec<usize> =
+                    report.split(';').filter_map(|n| n.parse().ok()).collect();
+                let is_press = bytes.get(end) == Some(&b'M');
+                if let (&[button, column, row], true) = (numbers.as_slice(), is_press) {
+                    // Bit 5 marks a drag, buttons 64 and above are the wheel
+                    match button & !32 {
+                        0 => events.push(Event::Paint {
+                            column,
+                            row,
+                            color: true,
+                        }),
+                        2 => events.push(Event::Paint {
+                            column,
+                            row,
+                            color: false,
+                        }),
+                        _ => {}
+                    }
+                }
+                i = end + 1;
+                continue;
+            }
+            [0x1b, b'[', b'A', ..] => events.push(Event::Move(0, -1)),
+            [0x1b, b'[', b'B', ..] => events.push(Event::Move(0, 1)),
+            [0x1b, b'[', b'C', ..] => events.push(Event::Move(1, 0)),
+            [0x1b, b'[', b'D', ..] => events.push(Event::Move(-1, 0)),
+            [0x1b, b'[', ..] => {}
+            [0x1b] | [b'q', ..] | [0x03, ..] => events.push(Event::Quit),
+            [b'k', ..] => events.push(Event::Move(0, -1)),
+            [b'j', ..] => events.push(Event::Move(0, 1)),
+            [b'l', ..] => events.push(Event::Move(1, 0)),
+            [b'h', ..] => events.push(Event::Move(-1, 0)),
+            [b' ', ..] => events.push(Event::Toggle),
+            [b't', ..] => events.push(Event::Set(true)),
+            [b'f', ..] => events.push(Event::Set(false)),
+            _ => {}
+        }
+        // Escape sequences of keys are three bytes long
+        i += if bytes[i] == 0x1b { 3 } else { 1 };
+    }
+    events
+}
+
+impl Cli<'_> {
+    /// Runs the full-screen editor until `q` is pressed
+    pub fn edit(&mut self) -> Result<(), String> {
+        if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+            return Err("the editor needs a terminal".to_owned());
+        }
+        let _screen = Screen::enter()?;
+        let (mut x, mut y): (usize, usize) = (0, 0);
+        // Top-left visible pixel
+        let (mut offset_x, mut offset_y) = (0, 0);
+        let mut buffer = [0; 256];
+        loop {
+            // Scroll to keep the cursor visible
+            let (visible_width, visible_height) = Cli::editor_size(term::size());
+            offset_x = offset_x.clamp((x + 1).saturating_sub(visible_width), x);
+            offset_y = offset_y.clamp((y + 1).saturating_sub(visible_height), y);
+            self.draw_editor(
+                (x, y),
+                (offset_x, offset_y),
+                (visible_width, visible_height),
+            );
+            let read = std::io::stdin()
+                .read(&mut buffer)
+                .map_err(|e| format!("failed to read the terminal: {e}"))?;
+            if read == 0 {
+                return Ok(());
+            }
+            for event in parse_events(&buffer[..read]) {
+                match event {
+                    Event::Move(dx, dy) => {
+                        x = x
+                            .saturating_add_signed(dx as isize)
+                            .min(self.image.get_width() - 1);
+                        y = y
+                            .saturating_add_signed(dy as isize)
+                            .min(self.image.get_height() - 1);
+                    }
+                    Event::Toggle => {
+                        let color = !self.image.grid[y][x];
+                        self.paint(x, y, color);
+                    }
+                    Event::Set(color) => self.paint(x, y, color),
+                    Event::Paint { column, row, color } => {
+                        // The frame takes the first row and column, and pixels are two columns wide
+                        if column < 2 || row < 2 {
+                            continue;
+                        }
+                        let pixel_x = offset_x + (column - 2) / 2;
+                        let pixel_y = offset_y + row - 2;
+                        if pixel_x < self.image.get_width().min(offset_x + visible_width)
+                            && pixel_y < self.image.get_height().min(offset_y + visible_height)
+                        {
+                            (x, y) = (pixel_x, pixel_y);
+                            if self.image.grid[y][x] != color {
+                                self.paint(x, y, color);
+                            }
+                        }
+                    }
+                    Event::Quit => return Ok(()),
+                }
+            }
+        }
+    }
+    /// Sets a pixel with a logged `write` command
+    fn paint(&mut self, x: usize, y: usize, color: bool) {
+        let color = if color { "t" } else { "f" };
+        // Pixels are set whatever the draw mode
+        let mode = if self.image.draw_mode == DrawMode::Set {
+            ""
+        } else {
+            " set"
+        };
+        self.parse_command(format!("write {x} {y} {color}{mode}"));
+    }
+    /// Returns the number of pixels fitting on a screen of `columns` * `rows` characters,
+    /// horizontally and vertically
+    fn editor_size((columns, rows): (usize, usize)) -> (usize, usize) {
+        // Keep room for the frame and the status line
+        (
+            (columns.saturating_sub(2) / 2).max(1),
+            rows.saturating_sub(3).max(1),
+        )
+    }
+    /// Draws the `visible_size` pixels from `offset` in the `blocks` mode of the theme, with the
+    /// cursor in reverse video, then the status line
+    fn draw_editor(
+        &self,
+        cursor: (usize, usize),
+        offset: (usize, usize),
+        (visible_width, visible_height): (usize, usize),
+    ) {
+        let end_x = self.image.get_width().min(offset.0 + visible_width);
+        let end_y = self.image.get_height().min(offset.1 + visible_height);
+        let theme = &self.render_options.theme;
+        let colors = theme.colors(term::color_support(true));
+        let [top_left, top_right, bottom_left, bottom_right, horizontal, vertical] =
+            theme.frame.characters();
+        let line = horizontal.to_string().repeat(2 * (end_x - offset.0));
+        let mut screen = String::from("\x1b[H\x1b[2J");
+        screen += &format!("{top_left}{line}{top_right}\r\n");
+        for y in offset.1..end_y {
+            screen += &format!("{vertical}{colors}");
+            for x in offset.0..end_x {
+                let cell = self.image.render_cell(RenderMode::Blocks, theme, x, y);
+                if (x, y) == cursor {
+                    screen += &format!("\x1b[7m{cell}\x1b[27m");
+                } else {
+                    screen += &cell;
+                }
+            }
+            screen += &format!("\x1b[0m{vertical}\r\n");
+        }
+        screen += &format!("{bottom_left}{line}{bottom_right}\r\n");
+        let (x, y) = cursor;
+        let color = if self.image.grid[y][x] { "t" } else { "f" };
+        screen += &format!("({x}, {y}) {color} | {HELP_LINE}");
+        print!("{screen}");
+        let _ = std::io::stdout().flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_events, Event};
+
+    fn paint(column: usize, row: usize, color: bool) -> Event {
+        Event::Paint { column, row, color }
+    }
+
+    #[test]
+    fn keys() {
+        assert_eq!(
+            parse_events(b"hjkl tfq\x03x"),
+            [
+                Event::Move(-1, 0),
+                Event::Move(0, 1),
+                Event::Move(0, -1),
+                Event::Move(1, 0),
+                Event::Toggle,
+                Event::Set(true),
+                Event::Set(false),
+                Event::Quit,
+                Event::Quit
+            ]
+        );
+    }
+
+    #[test]
+    fn escape_sequences() {
+        assert_eq!(
+            parse_events(b"\x1b[A\x1b[B\x1b[C\x1b[D"),
+            [
+                Event::Move(0, -1),
+                Event::Move(0, 1),
+                Event::Move(1, 0),
+                Event::Move(-1, 0)
+            ]
+        );
+        // Only an escape read on its own is the escape key
+        assert_eq!(parse_events(b"\x1b"), [Event::Quit]);
+        assert_eq!(parse_events(b"j\x1b"), [Event::Move(0, 1), Event::Quit]);
+        assert_eq!(parse_events(b"\x1b[Z\x1b[H"), []);
+        assert_eq!(parse_events(b"\x1bOPt"), [Event::Set(true)]);
+    }
+
+    #[test]
+    fn mouse_reports() {
+        // Left and right buttons, pressed then dragged
+        assert_eq!(
+            parse_events(b"\x1b[<0;12;5M\x1b[<32;14;5M\x1b[<2;3;4M\x1b[<34;120;40M"),
+            [
+                paint(12, 5, true),
+                paint(14, 5, true),
+                paint(3, 4, false),
+                paint(120, 40, false)
+            ]
+        );
+        // Releases, the middle button, plain moves and the wheel are ignored
+        assert_eq!(
+            parse_events(b"\x1b[<0;12;5m\x1b[<1;2;2M\x1b[<35;2;2M\x1b[<64;2;2M\x1b[<65;2;2M"),
+            []
+        );
+        // Keys around reports are still read
+        assert_eq!(
+            parse_events(b"t\x1b[<0;2;3Mq"),
+            [Event::Set(true), paint(2, 3, true), Event::Quit]
+        );
+    }
+
+    #[test]
+    fn split_mouse_reports() {
+        // A report cut at the end of a read is dropped rather than read as keys
+        assert_eq!(parse_events(b"\x1b[<0;12;5"), []);
+        assert_eq!(parse_events(b"j\x1b[<32;1"), [Event::Move(0, 1)]);
+        // Its end, read next, is ignored like unknown keys
+        assert_eq!(parse_events(b"2;5M"), []);
+        // Malformed reports are skipped whole
+        assert_eq!(
+            parse_events(b"\x1b[<0;12M\x1b[<a;b;cMt"),
+            [Event::Set(true)]
+        );
+    }
+}
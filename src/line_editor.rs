@@ -0,0 +1,435 @@
+//! Editing of the prompt line, with history, search and completion
+//!
+//! Lines are read with `read_line` when the standard input isn't a terminal.
+
+use crate::{term, COMMANDS, COMMAND_ALIASES};
+use std::collections::VecDeque;
+use std::io::{IsTerminal, Read, Write};
+use std::path::PathBuf;
+
+/// A key read from the terminal
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Key {
+    Char(char),
+    /// A letter pressed with Control
+    Control(char),
+    Enter,
+    Tab,
+    Backspace,
+    Delete,
+    Escape,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+}
+
+/// Decodes the keys in `bytes`, ignoring unknown escape sequences
+fn parse_keys(bytes: &[u8]) -> Vec<Key> {
+    let mut keys: Vec<Key> = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let length = match bytes[i..] {
+            [0x1b, b'[' | b'O', ..] => {
+                // CSI and SS3 sequences end with a byte from `@` to `~`
+                let length = bytes[i + 2..]
+                    .iter()
+                    .position(|byte| (b'@'..=b'~').contains(byte))
+                    .map_or(bytes.len() - i, |end| end + 3);
+                match &bytes[i + 2..i + length] {
+                    b"A" => keys.push(Key::Up),
+                    b"B" => keys.push(Key::Down),
+                    b"C" => keys.push(Key::Right),
+                    b"D" => keys.push(Key::Left),
+                    b"H" | b"1~" | b"7~" => keys.push(Key::Home),
+                    b"F" | b"4~" | b"8~" => keys.push(Key::End),
+                    b"3~" => keys.push(Key::Delete),
+                    _ => {}
+                }
+                length
+            }
+            [0x1b, ..] => {
+                keys.push(Key::Escape);
+                1
+            }
+            [b'\r' | b'\n', ..] => {
+                keys.push(Key::Enter);
+                1
+            }
+            [b'\t', ..] => {
+                keys.push(Key::Tab);
+                1
+            }
+            [0x7f | 0x08, ..] => {
+                keys.push(Key::Backspace);
+                1
+            }
+            [byte @ 0x01..=0x1a, ..] => {
+                keys.push(Key::Control((b'a' + byte - 1) as char));
+                1
+            }
+            [byte, ..] => {
+                // Characters take from one to four bytes in UTF-8
+                let length = match byte {
+                    0xf0.. => 4,
+                    0xe0.. => 3,
+                    0xc0.. => 2,
+                    _ => 1,
+                }
+                .min(bytes.len() - i);
+                if let Ok(text) = std::str::from_utf8(&bytes[i..i + length]) {
+                    keys.extend(text.chars().map(Key::Char));
+                }
+                length
+            }
+            [] => break,
+        };
+        i += length;
+    }
+    keys
+}
+
+/// Returns the longest prefix shared by all `words`
+fn common_prefix(words: &[String]) -> String {
+    let Some(first) = words.first() else {
+        return String::new();
+    };
+    let mut prefix: String = first.to_owned();
+    for word in &words[1..] {
+        while !word.starts_with(&prefix) {
+            prefix.pop();
+        }
+    }
+    prefix
+}
+
+/// Returns the file paths starting with `prefix`, directories ending with `/`
+fn complete_path(prefix: &str) -> Vec<String> {
+    let (directory, name) = match prefix.rfind('/') {
+        Some(i) => (&prefix[..=i], &prefix[i + 1..]),
+        None => ("", prefix),
+    };
+    let Ok(entries) = std::fs::read_dir(if directory.is_empty() { "." } else { directory }) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<String> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            // Hidden files are only completed when asked for
+            if !file_name.starts_with(name)
+                || (file_name.starts_with('.') && !name.starts_with('.'))
+            {
+                return None;
+            }
+            let is_directory = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+            Some(format!(
+                "{directory}{file_name}{}",
+                if is_directory { "/" } else { "" }
+            ))
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// Reader of lines from the terminal, remembering the previous ones
+///
+/// The default editor keeps no history.
+#[derive(Default)]
+pub struct LineEditor {
+    history: Vec<String>,
+    /// Number of lines kept in the history
+    history_size: usize,
+    /// File the history is loaded from and saved to
+    history_path: Option<PathBuf>,
+    /// Names completed along with commands
+    pub aliases: Vec<String>,
+    /// Keys read after the end of the previous line, as when several lines are pasted
+    pending_keys: VecDeque<Key>,
+}
+
+impl LineEditor {
+    /// Returns an editor with the last `history_size` lines of previous sessions, kept in
+    /// `~/.ipcli_history`
+    pub fn load(history_size: usize) -> LineEditor {
+        let history_path =
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".ipcli_history"));
+        let mut history: Vec<String> = history_path
+            .as_ref()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .map(|text| text.lines().map(str::to_owned).collect())
+            .unwrap_or_default();
+        history.drain(..history.len().saturating_sub(history_size));
+        LineEditor {
+            history,
+            history_size,
+            history_path,
+            ..LineEditor::default()
+        }
+    }
+    /// Adds `line` to the history and saves it, unless it is empty or repeats the last line
+    fn remember(&mut self, line: &str) {
+        if line.trim().is_empty() || self.history.last().is_some_and(|last| last == line) {
+            return;
+        }
+        self.history.push(line.to_owned());
+        let excess = self.history.len().saturating_sub(self.history_size);
+        self.history.drain(..excess);
+        if let Some(path) = &self.history_path {
+            let mut text = self.history.join("\n");
+            text.push('\n');
+            if let Err(e) = std::fs::write(path, text) {
+                eprintln!(
+                    "\r{}",
+                    term::warning(&format!("failed to save the history: {e}"))
+                );
+                self.history_path = None;
+            }
+        }
+    }
+    /// Prints `prompt` and returns the line typed, without the line break, or `None` at the end
+    /// of the input
+    pub fn read_line(&mut self, prompt: &str) -> Option<String> {
+        print!("{prompt}");
+        std::io::stdout().flush().unwrap();
+        if !std::io::stdin().is_terminal() {
+            return LineEditor::read_plain_line();
+        }
+        let Ok(_raw_mode) = term::RawMode::enable() else {
+            return LineEditor::read_plain_line();
+        };
+        let line = self.edit_line(prompt);
+        if let Some(line) = &line {
+            self.remember(line);
+        }
+        line
+    }
+    fn read_plain_line() -> Option<String> {
+        let mut line = String::new();
+        let read = std::io::stdin()
+            .read_line(&mut line)
+            .expect("Failed to read line");
+        if read == 0 {
+            return None;
+        }
+        Some(line.trim_end_matches(['\n', '\r']).to_owned())
+    }
+    /// Reads keys and edits the line until Enter is pressed, the terminal being in raw mode
+    fn edit_line(&mut self, prompt: &str) -> Option<String> {
+        let mut line: Vec<char> = Vec::new();
+        let mut cursor = 0;
+        // Position in the history when browsing it, and the line being typed before
+        let mut history_index = self.history.len();
+        let mut typed_line: Vec<char> = Vec::new();
+        loop {
+            let Some(key) = self.next_key() else {
+                print!("\r\n");
+                return None;
+            };
+            match key {
+                Key::Char(character) => {
+                    line.insert(cursor, character);
+                    cursor += 1;
+                }
+                Key::Enter => {
+                    print!("\r\n");
+                    std::io::stdout().flush().unwrap();
+                    return Some(line.into_iter().collect());
+                }
+                Key::Control('d') if line.is_empty() => {
+                    print!("\r\n");
+                    return None;
+                }
+                Key::Control('c') => {
+                    // Abandon the line
+                    print!("^C\r\n{prompt}");
+                    line.clear();
+                    cursor = 0;
+                    history_index = self.history.len();
+                }
+                Key::Backspace if cursor > 0 => {
+                    cursor -= 1;
+                    line.remove(cursor);
+                }
+                Key::Delete | Key::Control('d') if cursor < line.len() => {
+                    line.remove(cursor);
+                }
+                Key::Left | Key::Control('b') => cursor = cursor.saturating_sub(1),
+                Key::Right | Key::Control('f') => cursor = (cursor + 1).min(line.len()),
+                Key::Home | Key::Control('a') => cursor = 0,
+                Key::End | Key::Control('e') => cursor = line.len(),
+                Key::Control('u') => {
+                    line.drain(..cursor);
+                    cursor = 0;
+                }
+                Key::Control('k') => line.truncate(cursor),
+                Key::Control('w') => {
+                    // Delete the word before the cursor, and the spaces after it
+                    let mut start = cursor;
+                    while start > 0 && line[start - 1] == ' ' {
+                        start -= 1;
+                    }
+                    while start > 0 && line[start - 1] != ' ' {
+                        start -= 1;
+                    }
+                    line.drain(start..cursor);
+                    cursor = start;
+                }
+                Key::Up | Key::Control('p') if history_index > 0 => {
+                    if history_index == self.history.len() {
+                        typed_line = line.clone();
+                    }
+                    history_index -= 1;
+                    line = self.history[history_index].chars().collect();
+                    cursor = line.len();
+                }
+                Key::Down | Key::Control('n') if history_index < self.history.len() => {
+                    history_index += 1;
+                    line = match self.history.get(history_index) {
+                        Some(entry) => entry.chars().collect(),
+                        None => typed_line.clone(),
+                    };
+                    cursor = line.len();
+                }
+                Key::Tab => self.complete(prompt, &mut line, &mut cursor),
+                Key::Control('r') => {
+                    if let Some(found) = self.search(prompt) {
+                        line = found.chars().collect();
+                        cursor = line.len();
+                    }
+                }
+                _ => {}
+            }
+            // Pasted text is drawn once
+            if self.pending_keys.is_empty() {
+                LineEditor::redraw(prompt, &line, cursor);
+            }
+        }
+    }
+    /// Returns the next key pressed, or `None` at the end of the input
+    fn next_key(&mut self) -> Option<Key> {
+        let mut buffer = [0; 256];
+        while self.pending_keys.is_empty() {
+            let read = std::io::stdin().read(&mut buffer).unwrap_or(0);
+            if read == 0 {
+                return None;
+            }
+            self.pending_keys.extend(parse_keys(&buffer[..read]));
+        }
+        self.pending_keys.pop_front()
+    }
+    /// Prints the line over the current one, then puts the cursor back in place
+    fn redraw(prompt: &str, line: &[char], cursor: usize) {
+        let text: String = line.iter().collect();
+        print!("\r{prompt}{text}\x1b[K");
+        if cursor < line.len() {
+            print!("\x1b[{}D", line.len() - cursor);
+        }
+        std::io::stdout().flush().unwrap();
+    }
+    /// Completes the word before the cursor, a command name if it is the first word of the line
+    /// and a file path otherwise
+    ///
+    /// The words completing it are listed when it can't be lengthened.
+    fn complete(&self, prompt: &str, line: &mut Vec<char>, cursor: &mut usize) {
+        let start = line[..*cursor]
+            .iter()
+            .rposition(|character| character.is_whitespace())
+            .map_or(0, |i| i + 1);
+        let word: String = line[start..*cursor].iter().collect();
+        let is_command = line[..start]
+            .iter()
+            .all(|character| character.is_whitespace());
+        // Paths may be quoted, as in `include`
+        let quote = if !is_command && word.starts_with('"') {
+            "\""
+        } else {
+            ""
+        };
+        let prefix = &word[quote.len()..];
+        let candidates: Vec<String> = if is_command {
+            let lowercase_prefix = prefix.to_lowercase();
+            let mut names: Vec<String> = COMMANDS
+                .iter()
+                .chain(COMMAND_ALIASES.iter().map(|(_, alias)| alias))
+                .copied()
+                .chain(self.aliases.iter().map(String::as_str))
+                .filter(|name| name.starts_with(&lowercase_prefix))
+                .map(str::to_owned)
+                .collect();
+            names.sort();
+            names.dedup();
+            names
+        } else {
+            complete_path(prefix)
+        };
+        let mut completion = common_prefix(&candidates);
+        if candidates.len() == 1 && !completion.ends_with('/') {
+            completion = format!("{completion}{quote} ");
+        }
+        if completion.len() > prefix.len() && completion.starts_with(prefix) {
+            let added: Vec<char> = completion[prefix.len()..].chars().collect();
+            line.splice(*cursor..*cursor, added.iter().copied());
+            *cursor += added.len();
+        } else if candidates.len() > 1 {
+            print!("\r\n{}\r\n{prompt}", candidates.join("  "));
+        }
+    }
+    /// Searches the history backwards for lines containing what is typed, until a key other than
+    /// Control-R, Backspace or a character is pressed
+    ///
+    /// Returns the line found, or `None` if there is none or the search is cancelled with
+    /// Control-C or Control-G.
+    /// The key ending the search, except Escape, is then applied to the line.
+    fn search(&mut self, prompt: &str) -> Option<String> {
+        let mut query = String::new();
+        // Index of the line found, searching before it when Control-R is pressed again
+        let mut found: Option<usize> = None;
+        loop {
+            let found_line = found.map_or("", |i| self.history[i].as_str());
+            let status = if found.is_some() || query.is_empty() {
+                "reverse-i-search"
+            } else {
+                "failing reverse-i-search"
+            };
+            print!("\r({status})'{query}': {found_line}\x1b[K");
+            std::io::stdout().flush().unwrap();
+            let Some(key) = self.next_key() else {
+                print!("\r{prompt}");
+                return None;
+            };
+            let search_before = match key {
+                Key::Char(character) => {
+                    query.push(character);
+                    found.map_or(self.history.len(), |i| i + 1)
+                }
+                Key::Backspace => {
+                    query.pop();
+                    self.history.len()
+                }
+                Key::Control('r') => found.unwrap_or(self.history.len()),
+                Key::Control('c' | 'g') => {
+                    print!("\r{prompt}");
+                    return None;
+                }
+                _ => {
+                    if key != Key::Escape {
+                        self.pending_keys.push_front(key);
+                    }
+                    print!("\r{prompt}");
+                    return found.map(|i| self.history[i].to_owned());
+                }
+            };
+            let older = self.history[..search_before]
+                .iter()
+                .rposition(|line| !query.is_empty() && line.contains(&query));
+            // Searching again keeps the line found if there is no older one
+            if older.is_some() || key != Key::Control('r') {
+                found = older;
+            }
+        }
+    }
+}
@@ -1,56 +1,287 @@
+mod assertions;
+mod components;
+mod config;
+mod contours;
+mod dither;
+mod editor;
+mod line_editor;
+mod morphology;
+mod pattern;
+mod pnm;
+mod reconstruct;
+mod render;
+mod script;
+mod svg;
+mod term;
+mod theme;
+mod viewport;
+
+use morphology::StructuringElement;
+use pattern::Pattern;
+use render::{RenderMode, RenderOptions};
+use theme::{Color, FrameStyle, Theme};
+use viewport::Viewport;
+
 fn main() {
-    const RED: &str = "\x1b[31m";
-    const RESET: &str = "\x1b[0m";
-    let image_width: i32;
-    let image_height: i32;
-    let image_color: bool;
-    let mut run_script = false;
     let args: Vec<_> = std::env::args().collect();
-    if args.len() == 4 {
-        let w: Result<i32, _> = args[1].parse();
-        let h: Result<i32, _> = args[2].parse();
-        let c: Result<bool, _> = args[3]
-            .to_lowercase()
-            .replace("t", "true")
-            .replace("f", "false")
-            .parse();
-        if let (Ok(w), Ok(h), Ok(c)) = (w, h, c) {
-            image_width = w;
-            image_height = h;
-            image_color = c;
+    let mut canvas_arguments: Vec<&str> = Vec::new();
+    let mut script_path: Option<&str> = None;
+    let mut include_paths: Vec<std::path::PathBuf> = Vec::new();
+    let mut arguments: std::collections::HashMap<String, i64> = std::collections::HashMap::new();
+    let mut positional_arguments: Vec<i64> = Vec::new();
+    let mut batch = false;
+    let mut render_mode: Option<RenderMode> = None;
+    let mut config_path: Option<&str> = None;
+    let mut output_path: Option<&str> = None;
+    let mut i = 1;
+    while i < args.len() {
+        if args[i] == "-s" && i + 1 < args.len() && script_path.is_none() {
+            script_path = Some(&args[i + 1]);
+            i += 1;
+        } else if args[i] == "-I" && i + 1 < args.len() {
+            include_paths.push(args[i + 1].to_owned().into());
+            i += 1;
+        } else if args[i] == "--set" && i + 1 < args.len() {
+            let Some((name, value)) = args[i + 1].split_once('=') else {
+                exit_with_usage(&args[0]);
+            };
+            let Ok(value) = value.trim().parse() else {
+                exit_with_usage(&args[0]);
+            };
+            arguments.insert(name.trim().to_lowercase(), value);
+            i += 1;
+        } else if args[i] == "-o" && i + 1 < args.len() {
+            output_path = Some(&args[i + 1]);
+            i += 1;
+        } else if (args[i] == "-v" || args[i] == "--view") && i + 1 < args.len() {
+            let Some(mode) = RenderMode::from_name(&args[i + 1].to_lowercase()) else {
+                exit_with_usage(&args[0]);
+            };
+            render_mode = Some(mode);
+            i += 1;
+        } else if args[i] == "--config" && i + 1 < args.len() {
+            config_path = Some(&args[i + 1]);
+            i += 1;
+        } else if args[i] == "-b" || args[i] == "--batch" {
+            batch = true;
+        } else if args[i] == "--" {
+            for argument in &args[i + 1..] {
+                let Ok(value) = argument.parse() else {
+                    exit_with_usage(&args[0]);
+                };
+                positional_arguments.push(value);
+            }
+            break;
         } else {
-            eprintln!(
-                "{RED}{}: invalid options\n{RESET}usage: {} [w: number] [h: number] [color: {{t | f}}]",
-                args[0], args[0]
-            );
-            panic!();
-        }
-    } else if args.len() == 1 || (args.len() == 3 && args[1] == "-s") {
-        image_width = 10;
-        image_height = 10;
-        image_color = false;
-        if args.len() == 3 && args[1] == "-s" {
-            run_script = true;
+            canvas_arguments.push(&args[i]);
         }
-    } else {
-        eprintln!(
-            "{RED}{}: invalid options\n{RESET}usage: {} [w: number] [h: number] [color: {{t | f}}]\n",
-            args[0], args[0]
-        );
-        panic!();
+        i += 1;
+    }
+    let canvas = match canvas_arguments[..] {
+        [] => None,
+        [w, h, c] => match (w.parse(), h.parse(), parse_color(c)) {
+            (Ok(w), Ok(h), Some(c)) if is_valid_size(w, h) => Some((w, h, c)),
+            _ => exit_with_usage(&args[0]),
+        },
+        _ => exit_with_usage(&args[0]),
+    };
+    if script_path.is_none()
+        && (batch
+            || output_path.is_some()
+            || !(arguments.is_empty() && positional_arguments.is_empty()))
+    {
+        exit_with_usage(&args[0]);
     }
+    // A missing or invalid default configuration file only leaves the defaults
+    let config = match config_path {
+        Some(path) => config::Config::load(std::path::Path::new(path)).unwrap_or_else(|message| {
+            eprintln!("{}", term::error(&message));
+            std::process::exit(1);
+        }),
+        None => match config::Config::default_path().filter(|path| path.exists()) {
+            Some(path) => config::Config::load(&path).unwrap_or_else(|message| {
+                eprintln!("{}", term::warning(&message));
+                config::Config::default()
+            }),
+            None => config::Config::default(),
+        },
+    };
+    let (image_width, image_height, image_color) = canvas.unwrap_or(config.canvas);
     let mut img = Image::new(image_width, image_height, image_color);
-    let mut cli = Cli::new("ipcli> ".to_owned(), &mut img);
-    if run_script {
-        let file_path = args[2].to_owned();
-        let file_contents = std::fs::read_to_string(file_path).expect("failed to read script");
-        cli.parse_script(file_contents);
+    let mut cli = Cli::new(config.prompt.to_owned(), &mut img);
+    cli.canvas = (image_width, image_height, image_color);
+    cli.render_options.mode = render_mode.unwrap_or(config.render_mode);
+    cli.render_options.theme = config.theme.clone();
+    cli.line_editor = line_editor::LineEditor::load(config.history_size);
+    cli.line_editor.aliases = config.aliases.keys().cloned().collect();
+    cli.config = config;
+    // `-I` directories are searched before the ones in `IPCLI_PATH`
+    if let Some(paths) = std::env::var_os("IPCLI_PATH") {
+        include_paths.extend(std::env::split_paths(&paths));
+    }
+    cli.interpreter.include_paths = include_paths;
+    cli.interpreter.arguments = arguments;
+    cli.interpreter.positional_arguments = positional_arguments;
+    if let Some(file_path) = script_path {
+        // Canvas arguments take precedence over the script's `canvas` directive
+        cli.canvas_fixed = canvas.is_some();
+        cli.batch = batch;
+        cli.terminal_size = term::size();
+        let succeeded = cli.parse_script(std::path::Path::new(file_path));
+        cli.canvas_fixed = false;
+        if let Some(output_path) = output_path {
+            if let Err(message) = pnm::write_pbm(output_path, &cli.image.grid) {
+                eprintln!("{}", term::error(&message));
+                std::process::exit(1);
+            }
+        }
+        if batch {
+            std::process::exit(if succeeded { 0 } else { 1 });
+        }
     }
     cli.start();
 }
 
+fn exit_with_usage(program: &str) -> ! {
+    eprintln!(
+        "{}\nusage: {program} [w: number] [h: number] [color: {{t | f}}] [-v [mode]] [--config [path: file path]] [-s [path: file path]] [-b] [-o [path: file path]] [-I [directory]]... [--set [name]=[value]]... [-- [values]...]",
+        term::error(&format!("{program}: invalid options"))
+    );
+    std::process::exit(1);
+}
+
+/// Parses `t`, `f`, `true` or `false`, in any case
+fn parse_color(text: &str) -> Option<bool> {
+    match text.to_lowercase().as_str() {
+        "t" | "true" => Some(true),
+        "f" | "false" => Some(false),
+        _ => None,
+    }
+}
+
+/// Parses the `x`, `y`, `w` and `h` arguments of a rectangle
+fn parse_rect(arguments: &[&str]) -> Result<Rect, String> {
+    let numbers: Vec<i32> = arguments
+        .iter()
+        .map(|argument| {
+            argument
+                .parse()
+                .map_err(|_| format!("'{argument}' is not a number"))
+        })
+        .collect::<Result<_, _>>()?;
+    match numbers[..] {
+        [_, _, w, h] if w < 1 || h < 1 => Err("width or height can't be smaller than 1".to_owned()),
+        [x, y, w, h] => Ok(Rect { x, y, w, h }),
+        _ => Err("a rectangle needs x, y, w and h".to_owned()),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum DrawMode {
+    /// Overwrites pixels with the drawing color
+    Set,
+    /// Clears the pixels covered by `true`
+    Clear,
+    /// Inverts the pixels covered by `true` (XOR)
+    Toggle,
+}
+
+impl DrawMode {
+    pub fn from_name(name: &str) -> Option<DrawMode> {
+        match name {
+            "set" => Some(DrawMode::Set),
+            "clear" => Some(DrawMode::Clear),
+            "toggle" | "xor" => Some(DrawMode::Toggle),
+            _ => None,
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            DrawMode::Set => "set",
+            DrawMode::Clear => "clear",
+            DrawMode::Toggle => "toggle",
+        }
+    }
+    /// Combines the current value of a pixel with the drawing color
+    fn apply(&self, pixel: bool, color: bool) -> bool {
+        match self {
+            DrawMode::Set => color,
+            DrawMode::Clear => pixel && !color,
+            DrawMode::Toggle => pixel ^ color,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Rect {
+    x: i32,
+    y: i32,
+    w: i32,
+    h: i32,
+}
+
+impl Rect {
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x
+            && y >= self.y
+            && x < self.x.saturating_add(self.w)
+            && y < self.y.saturating_add(self.h)
+    }
+    /// Returns the part of the rectangle inside `other`, which may be empty
+    fn intersection(&self, other: &Rect) -> Rect {
+        let (x, y) = (self.x.max(other.x), self.y.max(other.y));
+        let end_x = (self.x.saturating_add(self.w)).min(other.x.saturating_add(other.w));
+        let end_y = (self.y.saturating_add(self.h)).min(other.y.saturating_add(other.h));
+        Rect {
+            x,
+            y,
+            w: end_x.saturating_sub(x).max(0),
+            h: end_y.saturating_sub(y).max(0),
+        }
+    }
+}
+
+/// Which neighbours of a pixel are considered connected to it
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Connectivity {
+    /// Horizontal and vertical neighbours
+    Four,
+    /// Horizontal, vertical and diagonal neighbours
+    Eight,
+}
+
+impl Connectivity {
+    pub fn from_name(name: &str) -> Option<Connectivity> {
+        match name {
+            "4" => Some(Connectivity::Four),
+            "8" => Some(Connectivity::Eight),
+            _ => None,
+        }
+    }
+    pub fn offsets(&self) -> &'static [(i32, i32)] {
+        match self {
+            Connectivity::Four => &[(1, 0), (-1, 0), (0, 1), (0, -1)],
+            Connectivity::Eight => &[
+                (1, 0),
+                (-1, 0),
+                (0, 1),
+                (0, -1),
+                (1, 1),
+                (-1, 1),
+                (1, -1),
+                (-1, -1),
+            ],
+        }
+    }
+}
+
 struct Image {
     grid: Vec<Vec<bool>>,
+    draw_mode: DrawMode,
+    fill_pattern: Option<Pattern>,
+    selection: Option<Rect>,
+    /// Region outside of which drawing commands leave the image unchanged
+    clip: Option<Rect>,
 }
 
 impl Image {
@@ -65,7 +296,13 @@ impl Image {
                 grid[i as usize].push(color);
             }
         }
-        Image { grid }
+        Image {
+            grid,
+            draw_mode: DrawMode::Set,
+            fill_pattern: None,
+            selection: None,
+            clip: None,
+        }
     }
     fn get_width(&self) -> usize {
         self.grid[0].len()
@@ -75,7 +312,10 @@ impl Image {
     }
     pub fn resize(&mut self, w: i32, h: i32) {
         if w < 1 || h < 1 {
-            eprintln!("\x1b[33mwidth or height can't be smaller than 1\x1b[0m");
+            eprintln!(
+                "{}",
+                term::warning("width or height can't be smaller than 1")
+            );
             return;
         }
         match w.cmp(&(self.get_width() as i32)) {
@@ -124,29 +364,77 @@ impl Image {
         }
     }
     pub fn write_pixel(&mut self, x: i32, y: i32, color: bool) {
-        if x < 0 || y < 0 {
-            eprintln!("\x1b[33mcoordinates can't be smaller than 0\x1b[0m");
-            return;
-        }
-        if x >= self.grid[0].len() as i32 || y >= self.grid.len() as i32 {
+        if !self.is_inside(x, y) {
             return;
         }
         self.grid[y as usize][x as usize] = color;
     }
     pub fn read_pixel(&self, x: i32, y: i32) -> bool {
         if x < 0 || y < 0 {
-            panic!("\x1b[33mcoordinates can't be smaller than 0\x1b[0m");
+            panic!("{}", term::warning("coordinates can't be smaller than 0"));
         }
         self.grid[y as usize][x as usize]
     }
     pub fn flip_pixel(&mut self, x: i32, y: i32) {
-        if x < 0 || y < 0 {
-            eprintln!("\x1b[33mcoordinates can't be smaller than 0\x1b[0m");
+        if !self.is_inside(x, y) {
             return;
         }
         let a: bool = self.grid[y as usize][x as usize];
         self.write_pixel(x, y, !a);
     }
+    /// Whether (x, y) is a pixel of the image
+    fn is_inside(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && x < self.get_width() as i32 && y < self.get_height() as i32
+    }
+    /// Returns the part of the image inside the clipping region, if any, where shapes are drawn
+    fn drawable_area(&self) -> Rect {
+        let image = Rect {
+            x: 0,
+            y: 0,
+            w: self.get_width() as i32,
+            h: self.get_height() as i32,
+        };
+        match self.clip {
+            Some(clip) => image.intersection(&clip),
+            None => image,
+        }
+    }
+    /// Whether (x, y) is a pixel of the image inside the clipping region, if any
+    fn is_drawable(&self, x: i32, y: i32) -> bool {
+        self.is_inside(x, y) && self.clip.is_none_or(|clip| clip.contains(x, y))
+    }
+    /// Whether (x, y) is in the selection, or anywhere if there is no selection
+    fn is_selected(&self, x: i32, y: i32) -> bool {
+        match self.selection {
+            Some(selection) => selection.contains(x, y),
+            None => true,
+        }
+    }
+    /// Draws a pixel using the current draw mode
+    pub fn plot_pixel(&mut self, x: i32, y: i32, color: bool) {
+        // Shapes are clipped to the image and to the clipping region
+        if !self.is_drawable(x, y) {
+            return;
+        }
+        let pixel = self.grid[y as usize][x as usize];
+        self.grid[y as usize][x as usize] = self.draw_mode.apply(pixel, color);
+    }
+    /// Draws every pixel of a shape once, so that overlapping parts aren't toggled twice
+    fn plot_pixels(&mut self, mut pixels: Vec<(i32, i32)>, color: bool) {
+        pixels.sort_unstable();
+        pixels.dedup();
+        for (x, y) in pixels {
+            self.plot_pixel(x, y, color);
+        }
+    }
+    /// Draws a pixel of a filled shape using the current fill pattern
+    fn plot_fill_pixel(&mut self, x: i32, y: i32, color: bool) {
+        let color = match &self.fill_pattern {
+            Some(pattern) => pattern.at(x, y) == color,
+            None => color,
+        };
+        self.plot_pixel(x, y, color);
+    }
     pub fn get_pixel_coordinates(&self) -> Vec<(i32, i32)> {
         let mut pixel_coordinates: Vec<(i32, i32)> = Vec::new();
         for (y, line) in self.grid.iter().enumerate() {
@@ -162,31 +450,64 @@ impl Image {
         }
     }
     pub fn flood_fill(&mut self, x: i32, y: i32, color: bool) {
-        if x < 0 || y < 0 {
-            eprintln!("\x1b[33mcoordinates can't be smaller than 0\x1b[0m");
+        if !self.is_inside(x, y) {
             return;
         }
-        if self.read_pixel(x, y) == color {
+        if self.draw_mode == DrawMode::Set
+            && self.fill_pattern.is_none()
+            && self.read_pixel(x, y) == color
+        {
             // Fill colour is the same as existent colour
             return;
         }
-        fn flood_helper(img: &mut Image, x: i32, y: i32, color: bool) {
-            if x as usize > img.grid[0].len() - 1 || y as usize > img.grid.len() - 1 {
-                // Exit if out of bounds
-                return;
+        for (x, y) in self.flood_region(x, y, Connectivity::Four) {
+            self.plot_fill_pixel(x, y, color);
+        }
+    }
+    /// Returns the pixels of the same colour connected to (x, y)
+    fn flood_region(&self, x: i32, y: i32, connectivity: Connectivity) -> Vec<(i32, i32)> {
+        let mut visited = vec![vec![false; self.get_width()]; self.get_height()];
+        self.flood_region_unvisited(x, y, connectivity, &mut visited)
+    }
+    /// Like `flood_region`, skipping and marking pixels in `visited`
+    fn flood_region_unvisited(
+        &self,
+        x: i32,
+        y: i32,
+        connectivity: Connectivity,
+        visited: &mut [Vec<bool>],
+    ) -> Vec<(i32, i32)> {
+        let target = self.read_pixel(x, y);
+        let mut region: Vec<(i32, i32)> = Vec::new();
+        let mut stack = vec![(x, y)];
+        while let Some((x, y)) = stack.pop() {
+            if x < 0 || y < 0 || x >= self.get_width() as i32 || y >= self.get_height() as i32 {
+                // Skip if out of bounds
+                continue;
             }
-            if img.read_pixel(x, y) == color {
-                // Exit if pixel colour is the same as fill colour
-                return;
+            if visited[y as usize][x as usize] || self.read_pixel(x, y) != target {
+                // Skip if already visited or of another colour
+                continue;
             }
-            img.write_pixel(x, y, color);
+            visited[y as usize][x as usize] = true;
+            region.push((x, y));
             // Propagate to neighbouring pixels
-            flood_helper(img, x + 1, y, color);
-            flood_helper(img, x - 1, y, color);
-            flood_helper(img, x, y + 1, color);
-            flood_helper(img, x, y - 1, color);
+            for (dx, dy) in connectivity.offsets() {
+                stack.push((x + dx, y + dy));
+            }
         }
-        flood_helper(self, x, y, color);
+        region
+    }
+    /// Replaces the image with a dithered PBM, PGM or PPM file
+    pub fn import(
+        &mut self,
+        path: &str,
+        algorithm: dither::Algorithm,
+        serpentine: bool,
+    ) -> Result<(), String> {
+        let luminance = pnm::read_luminance(path)?;
+        self.grid = dither::dither(&luminance, algorithm, serpentine);
+        Ok(())
     }
     pub fn clear(&mut self, color: bool) {
         for x in 0..self.get_width() {
@@ -195,69 +516,156 @@ impl Image {
             }
         }
     }
-    fn get_human_readable(&self, fill_color: &str, background_color: &str, frame: bool) -> String {
-        let frame_vertical: &str;
-        let mut frame_horizontal = String::new();
-        if frame {
-            frame_vertical = "|";
-            frame_horizontal += "+";
-            for _ in 0..2 * self.get_width() {
-                frame_horizontal += "-";
-            }
-            frame_horizontal += "+";
+    /// Returns the image as text, `viewport` telling where its pixels are in the full image
+    ///
+    /// The colors of the theme are limited to `colors`.
+    fn get_human_readable(
+        &self,
+        options: &RenderOptions,
+        viewport: &Viewport,
+        colors: term::ColorSupport,
+    ) -> String {
+        let mode = options.mode;
+        let theme = &options.theme;
+        let [top_left, top_right, bottom_left, bottom_right, horizontal, vertical] =
+            theme.frame.characters();
+        let image_colors = theme.colors(colors);
+        let (cell_width, cell_height) = mode.cell_size();
+        let columns = self.get_width().div_ceil(cell_width);
+        let rows = self.get_height().div_ceil(cell_height);
+        // Pixels of the full image shown by each column and row of characters
+        let column_pixels: Vec<std::ops::Range<usize>> = (0..columns)
+            .map(|column| viewport.pixel_range(viewport.x, column * cell_width, cell_width))
+            .collect();
+        let row_pixels: Vec<std::ops::Range<usize>> = (0..rows)
+            .map(|row| viewport.pixel_range(viewport.y, row * cell_height, cell_height))
+            .collect();
+        let (column_ticks, row_ticks) = if options.rulers {
+            let label_width = column_pixels
+                .last()
+                .map_or(0, |range| range.end.to_string().len());
+            (
+                render::ruler_ticks(&column_pixels, mode.cell_width(), label_width + 1),
+                render::ruler_ticks(&row_pixels, 1, 2),
+            )
+        } else {
+            (vec![None; columns], vec![None; rows])
+        };
+        // Row labels are right-aligned left of the frame
+        let gutter = row_ticks
+            .iter()
+            .flatten()
+            .map(|tick| tick.to_string().len())
+            .max()
+            .unwrap_or(0);
+        let frame_vertical: String;
+        let mut frame_top = String::new();
+        let mut frame_bottom = String::new();
+        if options.frame {
+            frame_vertical = vertical.to_string();
+            let line = horizontal.to_string().repeat(columns * mode.cell_width());
+            frame_top = format!("{}{top_left}{line}{top_right}", " ".repeat(gutter));
+            frame_bottom = format!("{}{bottom_left}{line}{bottom_right}", " ".repeat(gutter));
         } else {
-            frame_vertical = "";
-            frame_horizontal = "".to_owned();
+            frame_vertical = "".to_owned();
         }
         let mut human_readable = String::new();
-        human_readable += &frame_horizontal;
+        if options.rulers {
+            let indent = gutter + frame_vertical.chars().count();
+            human_readable += &render::ruler_labels(&column_ticks, mode.cell_width(), indent);
+            human_readable += "\n";
+        }
+        human_readable += &frame_top;
         human_readable += "\n";
-        for line in &self.grid {
-            human_readable += frame_vertical;
-            for cell in line {
-                if cell == &true {
-                    human_readable += fill_color;
-                } else {
-                    human_readable += background_color;
+        for (row, (row_range, row_tick)) in row_pixels.iter().zip(&row_ticks).enumerate() {
+            match row_tick {
+                Some(tick) => human_readable += &format!("{tick:>gutter$}"),
+                None => human_readable += &" ".repeat(gutter),
+            }
+            human_readable += &frame_vertical;
+            human_readable += &image_colors;
+            for (column, column_range) in column_pixels.iter().enumerate() {
+                let cell = self.render_cell(mode, theme, column, row);
+                let is_empty = cell.chars().all(|glyph| glyph == ' ' || glyph == theme.off);
+                match options.overlay(column_range, row_range) {
+                    Some((overlay, filler)) if is_empty => {
+                        human_readable.push(overlay);
+                        for _ in 1..mode.cell_width() {
+                            human_readable.push(filler);
+                        }
+                    }
+                    _ => human_readable += &cell,
                 }
             }
-            human_readable += frame_vertical;
+            if !image_colors.is_empty() {
+                human_readable += "\x1b[0m";
+            }
+            human_readable += &frame_vertical;
             human_readable += "\n";
         }
-        human_readable += &frame_horizontal;
+        human_readable += &frame_bottom;
         human_readable
     }
-    pub fn print(&self, frame: bool) {
-        println!("{}", self.get_human_readable("██", "  ", frame));
+    pub fn print(&self, options: &RenderOptions, viewport: &Viewport) {
+        let colors = term::color_support(std::io::IsTerminal::is_terminal(&std::io::stdout()));
+        println!("{}", self.get_human_readable(options, viewport, colors));
     }
     pub fn draw_line(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, color: bool) {
-        // Compute equation
-        let dy = (y2 - y1) as f32;
-        let dx = (x2 - x1) as f32;
-        let plot_as_y_of_x = dx > dy;
-        let slope: f32 = if plot_as_y_of_x { dy / dx } else { dx / dy };
-        let initial_value = if plot_as_y_of_x {
-            y1 - (slope * x1 as f32) as i32
+        let pixels = Image::line_pixels(x1, y1, x2, y2, self.drawable_area());
+        self.plot_pixels(pixels, color);
+    }
+    /// Returns the pixels of the line from (x1, y1) to (x2, y2) that are inside `area`
+    fn line_pixels(x1: i32, y1: i32, x2: i32, y2: i32, area: Rect) -> Vec<(i32, i32)> {
+        let mut pixels: Vec<(i32, i32)> = Vec::new();
+        // Step along the longest side of the line, rounding the position on the other one
+        let steep = (y2 as i64 - y1 as i64).abs() > (x2 as i64 - x1 as i64).abs();
+        let ((major1, major2), (minor1, minor2)) = if steep {
+            ((y1 as i64, y2 as i64), (x1 as i64, x2 as i64))
         } else {
-            x1 - (slope * y1 as f32) as i32
+            ((x1 as i64, x2 as i64), (y1 as i64, y2 as i64))
         };
-        // Plot line
-        let range_start = if plot_as_y_of_x { x1 } else { y1 };
-        let range_end = if plot_as_y_of_x { x2 } else { y2 };
-        let range = if range_end > range_start {
-            range_start..range_end + 1
+        let (major_area, minor_area) = if steep {
+            ((area.y, area.h), (area.x, area.w))
         } else {
-            range_end..range_start + 1
+            ((area.x, area.w), (area.y, area.h))
         };
-        if plot_as_y_of_x {
-            for x in range {
-                self.write_pixel(x, (slope * x as f32 + initial_value as f32) as i32, color);
+        let length = (major2 - major1).abs();
+        let rise = (minor2 - minor1).abs();
+        let (major_step, minor_step) = ((major2 - major1).signum(), (minor2 - minor1).signum());
+        // Only visit the steps whose major coordinate is inside the area
+        let area_end = major_area.0 as i64 + major_area.1 as i64 - 1;
+        let steps_to = |coordinate: i64| (coordinate - major1) * major_step;
+        let (mut first, mut last) = (0, length);
+        if major_step != 0 {
+            let (enter, leave) = (steps_to(major_area.0 as i64), steps_to(area_end));
+            first = first.max(enter.min(leave));
+            last = last.min(enter.max(leave));
+        }
+        // Bresenham's error term at the first step: the minor offset of step k is
+        // (2 * k * rise + length) / (2 * length), rounding k * rise / length half up
+        let denominator = 2 * length.max(1) as i128;
+        let numerator = 2 * first.max(0) as i128 * rise as i128 + length as i128;
+        let mut offset = (numerator / denominator) as i64;
+        let mut error = (numerator % denominator) as i64;
+        let minor_range = minor_area.0 as i64..minor_area.0 as i64 + minor_area.1 as i64;
+        for step in first..=last {
+            let major = major1 + major_step * step;
+            let minor = minor1 + minor_step * offset;
+            if (major_area.0 as i64..=area_end).contains(&major) && minor_range.contains(&minor) {
+                let (major, minor) = (major as i32, minor as i32);
+                pixels.push(if steep {
+                    (minor, major)
+                } else {
+                    (major, minor)
+                });
             }
-        } else {
-            for y in range {
-                self.write_pixel((slope * y as f32 + initial_value as f32) as i32, y, color);
+            error += 2 * rise;
+            if error >= denominator as i64 {
+                error -= denominator as i64;
+                offset += 1;
             }
         }
+        pixels
     }
     #[allow(clippy::too_many_arguments)]
     pub fn draw_curve(
@@ -270,159 +678,433 @@ impl Image {
         y2: i32,
         color: bool,
     ) {
-        let x_coords = [x0, x1, x2];
-        let y_coords = [y0, y1, y2];
-        let start_x: i32 = x_coords.iter().min().unwrap().to_owned();
-        let end_x: i32 = x_coords.iter().max().unwrap().to_owned();
-        let start_y: i32 = y_coords.iter().min().unwrap().to_owned();
-        let end_y: i32 = y_coords.iter().max().unwrap().to_owned();
-        for x in start_x..end_x {
-            for y in start_y..end_y {
-                let mut is_on_curve = false;
-                // Sample several t values
-                for t in (0..=100).map(|x| x as f32 / 100.0) {
-                    let x_curve = (1.0 - t).powi(2) * x0 as f32
-                        + 2.0 * (1.0 - t) * t * x1 as f32
-                        + t.powi(2) * x2 as f32;
-                    let y_curve = (1.0 - t).powi(2) * y0 as f32
-                        + 2.0 * (1.0 - t) * t * y1 as f32
-                        + t.powi(2) * y2 as f32;
-                    // Check if the pixel is close to the curve point
-                    if (x_curve.round() as i32 == x) && (y_curve.round() as i32 == y) {
-                        is_on_curve = true;
-                        break;
-                    }
-                }
-                if is_on_curve {
-                    self.write_pixel(x, y, color);
-                }
-            }
+        let mut pixels: Vec<(i32, i32)> = Vec::new();
+        // Sample several t values, each drawing the pixel it falls in
+        for t in (0..=100).map(|x| x as f32 / 100.0) {
+            let x_curve = (1.0 - t).powi(2) * x0 as f32
+                + 2.0 * (1.0 - t) * t * x1 as f32
+                + t.powi(2) * x2 as f32;
+            let y_curve = (1.0 - t).powi(2) * y0 as f32
+                + 2.0 * (1.0 - t) * t * y1 as f32
+                + t.powi(2) * y2 as f32;
+            pixels.push((x_curve.round() as i32, y_curve.round() as i32));
         }
+        self.plot_pixels(pixels, color);
     }
     pub fn draw_rectangle(&mut self, x: i32, y: i32, w: i32, h: i32, color: bool) {
-        if x < 0 || y < 0 {
-            eprintln!("\x1b[33mcoordinates can't be smaller than 0\x1b[0m");
-            return;
-        }
         if w < 1 || h < 1 {
-            eprintln!("\x1b[33mwidth or height can't be smaller than 1\x1b[0m");
+            eprintln!(
+                "{}",
+                term::warning("width or height can't be smaller than 1")
+            );
             return;
         }
-        for i in x..(x + w) {
-            for j in y..(y + h) {
-                self.write_pixel(i, j, color);
+        // Only visit the part of the rectangle inside the image
+        let end_x = x.saturating_add(w).min(self.get_width() as i32);
+        let end_y = y.saturating_add(h).min(self.get_height() as i32);
+        for i in x.max(0)..end_x {
+            for j in y.max(0)..end_y {
+                self.plot_fill_pixel(i, j, color);
             }
         }
     }
     pub fn draw_rectangle_outline(&mut self, x: i32, y: i32, w: i32, h: i32, color: bool) {
+        let area = self.drawable_area();
+        let (end_x, end_y) = (x.saturating_add(w), y.saturating_add(h));
+        let mut pixels: Vec<(i32, i32)> = Vec::new();
         // Horizontal lines
-        self.draw_line(x, y, x + w, y, color);
-        self.draw_line(x, y + h, x + w, y + h, color);
+        pixels.append(&mut Image::line_pixels(x, y, end_x, y, area));
+        pixels.append(&mut Image::line_pixels(x, end_y, end_x, end_y, area));
         // Vertical lines
-        self.draw_line(x, y, x, y + h, color);
-        self.draw_line(x + w, y, x + w, y + h, color);
+        pixels.append(&mut Image::line_pixels(x, y, x, end_y, area));
+        pixels.append(&mut Image::line_pixels(end_x, y, end_x, end_y, area));
+        self.plot_pixels(pixels, color);
     }
     pub fn draw_circle(&mut self, xc: i32, yc: i32, radius: i32, color: bool) {
-        if xc < 0 || yc < 0 {
-            eprintln!("\x1b[33mcoordinates can't be smaller than 0\x1b[0m");
-            return;
-        }
         if radius < 0 {
-            eprintln!("\x1b[33mradius can't be smaller than 0\x1b[0m");
+            eprintln!("{}", term::warning("radius can't be smaller than 0"));
             return;
         }
-        // Check if the pixels in a square of side (2 * radius) are included in the circle
-        let start_x = xc - radius;
-        let end_x = xc + radius;
-        let start_y = yc - radius;
-        let end_y = yc + radius;
-        for x in start_x + 1..end_x {
-            for y in start_y + 1..end_y {
-                // Increment start_x&y by one to correct rounding error
-                if (x - xc).pow(2) + (y - yc).pow(2) < radius.pow(2) {
-                    // In circle
-                    self.write_pixel(x, y, color);
+        // Fill each row of the area between the two ends of the outline
+        let area = self.drawable_area();
+        let radius = radius as i64;
+        for y in area.y..area.y + area.h {
+            let dy = (y as i64 - yc as i64).abs();
+            let x = Image::octant_x(radius, dy);
+            let half_width = if x >= dy {
+                x
+            } else {
+                // Above the octant, the row ends at the last column whose outline is this high
+                match radius * radius - 1 - dy * dy + dy {
+                    c if c < 0 => continue,
+                    c => c.isqrt(),
                 }
+            };
+            let start_x = (xc as i64 - half_width).max(area.x as i64) as i32;
+            let end_x = (xc as i64 + half_width).min((area.x + area.w - 1) as i64) as i32;
+            for x in start_x..=end_x {
+                self.plot_fill_pixel(x, y, color);
             }
         }
     }
     pub fn draw_circle_outline(&mut self, xc: i32, yc: i32, radius: i32, color: bool) {
-        if xc < 0 || yc < 0 {
-            eprintln!("\x1b[33mcoordinates can't be smaller than 0\x1b[0m");
-            return;
-        }
         if radius < 0 {
-            eprintln!("\x1b[33mradius can't be smaller than 0\x1b[0m");
+            eprintln!("{}", term::warning("radius can't be smaller than 0"));
             return;
         }
-        // Draw a circle outline using Jesko's method
-        let mut t1 = radius / 16;
-        let mut x = radius;
-        let mut y = 0;
-        let mut t2: i32;
-        while x >= y {
-            self.write_pixel(xc + x, yc + y, color);
-            self.write_pixel(xc - x, yc + y, color);
-            self.write_pixel(xc + x, yc - y, color);
-            self.write_pixel(xc - x, yc - y, color);
-            self.write_pixel(yc + y, xc + x, color);
-            self.write_pixel(yc + y, xc - x, color);
-            self.write_pixel(yc - y, xc + x, color);
-            self.write_pixel(yc - y, xc - x, color);
-            y += 1;
-            t1 += y;
-            t2 = t1 - x;
-            if t2 >= 0 {
-                t1 = t2;
-                x -= 1;
+        let pixels = Image::circle_pixels(xc, yc, radius, self.drawable_area());
+        self.plot_pixels(pixels, color);
+    }
+    /// Returns the pixels of a circle outline inside `area`, as drawn by the midpoint algorithm
+    ///
+    /// The pixels of each row and column of the area are computed directly, so that only the
+    /// visible part of the circle is visited.
+    fn circle_pixels(xc: i32, yc: i32, radius: i32, area: Rect) -> Vec<(i32, i32)> {
+        let mut pixels: Vec<(i32, i32)> = Vec::new();
+        let (xc, yc, radius) = (xc as i64, yc as i64, radius as i64);
+        // Distances to the centre of the rows, then of the columns
+        let rows = (area.y..area.y + area.h).map(|y| (y as i64 - yc).abs());
+        let columns = (area.x..area.x + area.w).map(|x| (x as i64 - xc).abs());
+        for offset in rows.chain(columns) {
+            let x = Image::octant_x(radius, offset);
+            if x < offset {
+                continue;
+            }
+            // Mirror the octant from 0° to 45° into the seven others
+            for (dx, dy) in [(x, offset), (offset, x)] {
+                for (x, y) in [
+                    (xc + dx, yc + dy),
+                    (xc - dx, yc + dy),
+                    (xc + dx, yc - dy),
+                    (xc - dx, yc - dy),
+                ] {
+                    if let (Ok(x), Ok(y)) = (i32::try_from(x), i32::try_from(y)) {
+                        if area.contains(x, y) {
+                            pixels.push((x, y));
+                        }
+                    }
+                }
             }
         }
+        pixels.sort_unstable();
+        pixels.dedup();
+        pixels
+    }
+    /// Returns the x the midpoint algorithm picks for `y` in the octant from 0° to 45°, which is
+    /// the largest with x² - x + y² <= r² - 1, or -1 past the radius
+    fn octant_x(radius: i64, y: i64) -> i64 {
+        if y == 0 {
+            return radius;
+        }
+        match radius * radius - 1 - y * y {
+            c if c < 0 => -1,
+            c => ((1 + (1 + 4 * c as i128).isqrt()) / 2) as i64,
+        }
+    }
+}
+
+/// Commands that accept a trailing draw mode
+const DRAWING_COMMANDS: [&str; 16] = [
+    "write",
+    "w",
+    "fill",
+    "f",
+    "draw_rectangle",
+    "dr",
+    "draw_rectangle_outline",
+    "dro",
+    "draw_line",
+    "dl",
+    "draw_curve",
+    "db",
+    "draw_circle",
+    "dc",
+    "draw_circle_outline",
+    "dco",
+];
+
+/// Full names of the commands
+const COMMANDS: [&str; 44] = [
+    "help",
+    "canvas",
+    "dump",
+    "let",
+    "write",
+    "fill",
+    "resize",
+    "clear",
+    "invert",
+    "mode",
+    "pattern",
+    "quit",
+    "draw_rectangle",
+    "draw_rectangle_outline",
+    "draw_line",
+    "draw_curve",
+    "draw_circle",
+    "draw_circle_outline",
+    "import",
+    "export",
+    "clip",
+    "select",
+    "dilate",
+    "erode",
+    "open",
+    "close",
+    "components",
+    "outline",
+    "contours",
+    "edit",
+    "view",
+    "zoom",
+    "pan",
+    "scroll",
+    "rulers",
+    "grid",
+    "crosshair",
+    "theme",
+    "config",
+    "hash",
+    "assert_pixel",
+    "assert_count",
+    "assert_equals",
+    "assert_hash",
+];
+
+/// Full names of the commands with a short alias
+const COMMAND_ALIASES: [(&str, &str); 17] = [
+    ("help", "h"),
+    ("canvas", "ca"),
+    ("dump", "d"),
+    ("write", "w"),
+    ("fill", "f"),
+    ("resize", "r"),
+    ("clear", "c"),
+    ("invert", "i"),
+    ("mode", "m"),
+    ("pattern", "p"),
+    ("quit", "q"),
+    ("draw_rectangle", "dr"),
+    ("draw_rectangle_outline", "dro"),
+    ("draw_line", "dl"),
+    ("draw_curve", "db"),
+    ("draw_circle", "dc"),
+    ("draw_circle_outline", "dco"),
+];
+
+/// Returns the full name of a command from its alias, or `name` if it has none
+fn full_command_name(name: &str) -> &str {
+    COMMAND_ALIASES
+        .iter()
+        .find(|(_, alias)| *alias == name)
+        .map_or(name, |(full_name, _)| full_name)
+}
+
+/// What an argument of a command stands for
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Argument {
+    /// A number, which may be given as an expression
+    Number,
+    /// A color, `t` or `f`
+    Color,
+    /// A name, keyword, path or anything else, taken as it is
+    Text,
+}
+
+/// Returns what the argument at `i` of the lowercase `command` stands for, procedures taking
+/// numbers
+fn argument_kind(command: &[&str], i: usize) -> Argument {
+    use Argument::{Color, Number, Text};
+    let name = full_command_name(command[0]);
+    let previous = if i > 1 { command[i - 1] } else { "" };
+    let is_keyword = ["none", "in", "out", "square", "cross", "disk", "load"].contains(&command[i]);
+    match (name, i) {
+        (_, 0) => Text,
+        (_, _) if !COMMANDS.contains(&name) => Number,
+        ("write" | "fill" | "canvas" | "resize", 1 | 2) => Number,
+        ("write" | "fill" | "canvas", 3) => Color,
+        ("clear" | "components" | "outline" | "contours" | "rulers" | "assert_count", 1) => Color,
+        ("draw_rectangle" | "draw_rectangle_outline" | "draw_line", 1..=4) => Number,
+        ("draw_rectangle" | "draw_rectangle_outline" | "draw_line", 5) => Color,
+        ("draw_curve", 1..=6) => Number,
+        ("draw_curve", 7) => Color,
+        ("draw_circle" | "draw_circle_outline", 1..=3) => Number,
+        ("draw_circle" | "draw_circle_outline", 4) => Color,
+        ("assert_pixel", 1 | 2) => Number,
+        ("assert_pixel", 3) => Color,
+        ("components" | "assert_count", 2) => Number,
+        ("components" | "outline", 3) => Number,
+        ("clip" | "select", 1..=4) | ("pan" | "scroll" | "crosshair", 1 | 2) if !is_keyword => {
+            Number
+        }
+        ("zoom" | "grid", 1) if !is_keyword => Number,
+        // Structuring element sizes and iteration counts
+        ("dilate" | "erode" | "open" | "close", _) if !is_keyword && previous != "load" => Number,
+        ("pattern", 2) if previous == "shade" => Number,
+        ("import", _) if previous == "threshold" => Number,
+        ("export", 4) if previous == "traced" => Number,
+        _ => Text,
+    }
+}
+
+/// A successfully executed command
+#[derive(Clone, PartialEq, Debug)]
+struct LogEntry {
+    /// The command as it was entered
+    raw: String,
+    /// The command with its full name, full draw mode names and `t` or `f` colors
+    canonical: String,
+}
+
+impl LogEntry {
+    fn new(raw: &str) -> LogEntry {
+        let lowercase_raw = raw.to_lowercase();
+        let lowercase_tokens: Vec<&str> = lowercase_raw.split_whitespace().collect();
+        // Only the command name, draw modes and colors are rewritten, paths and other arguments
+        // keep their case
+        let name = lowercase_tokens
+            .first()
+            .map_or("", |name| full_command_name(name));
+        let mut canonical: Vec<&str> = Vec::new();
+        for (i, token) in raw.split_whitespace().enumerate() {
+            // `mode` takes a draw mode, which drawing commands may end with
+            let is_draw_mode = (name == "mode" && i == 1)
+                || (DRAWING_COMMANDS.contains(&name) && i > 1 && i == lowercase_tokens.len() - 1);
+            canonical.push(match argument_kind(&lowercase_tokens, i) {
+                _ if i == 0 => name,
+                Argument::Text if is_draw_mode => {
+                    DrawMode::from_name(lowercase_tokens[i]).map_or(token, |mode| mode.name())
+                }
+                Argument::Color => match parse_color(token) {
+                    Some(true) => "t",
+                    Some(false) => "f",
+                    None => token,
+                },
+                _ => token,
+            });
+        }
+        LogEntry {
+            raw: raw.trim().to_owned(),
+            canonical: canonical.join(" "),
+        }
     }
 }
 
 struct Cli<'cli_lifetime> {
     prompt_string: String,
     image: &'cli_lifetime mut Image,
+    /// Commands executed during the session, including the ones from scripts
+    log: Vec<LogEntry>,
+    /// Size and color of the image before the first command of the log
+    canvas: (i32, i32, bool),
+    /// Whether `canvas` directives are ignored
+    canvas_fixed: bool,
+    interpreter: script::Interpreter,
+    /// Message of the last failed assertion, which stops the script
+    failure: Option<String>,
+    /// Message of the last invalid command, which stops the script in batch mode
+    error: Option<String>,
+    /// Whether the script runs in batch mode
+    batch: bool,
+    render_options: RenderOptions,
+    viewport: viewport::Viewport,
+    /// Columns and rows of the terminal, queried once before each prompt
+    terminal_size: (usize, usize),
+    line_editor: line_editor::LineEditor,
+    config: config::Config,
 }
 
 impl Cli<'_> {
-    pub fn new(prompt_string: String, image: &mut Image) -> Cli {
+    pub fn new(prompt_string: String, image: &mut Image) -> Cli<'_> {
         Cli {
             prompt_string,
             image,
+            log: Vec::new(),
+            canvas: (10, 10, false),
+            canvas_fixed: false,
+            interpreter: script::Interpreter::default(),
+            failure: None,
+            error: None,
+            batch: false,
+            render_options: RenderOptions::default(),
+            viewport: viewport::Viewport::default(),
+            terminal_size: term::DEFAULT_SIZE,
+            line_editor: line_editor::LineEditor::default(),
+            config: config::Config::default(),
         }
     }
     pub fn start(&mut self) {
         self.print_welcome_message();
-        let mut input_log: Vec<String> = vec![];
-        let mut input: String;
         let mut print_image = true;
         loop {
+            self.terminal_size = term::size();
             if print_image {
-                self.image.print(true);
+                self.print_image();
             }
             let old_image = self.image.grid.to_owned(); // Make a copy of the current image
-            print!("{}", self.prompt_string);
-            std::io::Write::flush(&mut std::io::stdout()).unwrap();
-            input = "".to_owned();
-            std::io::stdin()
-                .read_line(&mut input)
-                .expect("Failed to read line");
-            self.parse_command(input, &mut input_log);
-            print_image = old_image != self.image.grid; // Check wether the image has changed
+            let old_view = (self.viewport, self.render_options.clone());
+            let Some(mut input) = self.line_editor.read_line(&self.prompt_string) else {
+                // End of input
+                println!();
+                std::process::exit(0);
+            };
+            // Blocks can span several lines
+            while script::is_incomplete(&input) {
+                let Some(line) = self.line_editor.read_line("... ") else {
+                    break;
+                };
+                input += "\n";
+                input += &line;
+            }
+            if let Err(message) = self.run(&input) {
+                eprintln!("{}", term::warning(&message));
+            }
+            // Check wether the image or the way it is shown has changed
+            print_image = old_image != self.image.grid
+                || old_view != (self.viewport, self.render_options.clone());
         }
     }
-    fn parse_command(&mut self, input: String, input_log: &mut Vec<String>) {
+    fn parse_command(&mut self, input: String) {
+        let is_assignment =
+            input.split_whitespace().next().map(str::to_lowercase) == Some("let".to_owned());
+        let input = if is_assignment {
+            input
+        } else {
+            match self.expand_expressions(&input) {
+                Ok(expanded_input) => expanded_input,
+                Err(message) => {
+                    eprintln!("{}", term::warning(&message));
+                    self.error = Some(message);
+                    return;
+                }
+            }
+        };
         let original_input = input.to_owned();
+        let verbatim_command = original_input.split_whitespace().collect::<Vec<&str>>();
         let mut input = input;
         input = input.to_lowercase();
-        input = input.replace(" t", " true"); // It's a hack, but it works
-        input = input.replace(" f", " false");
-        let command = input.split_whitespace().collect::<Vec<&str>>();
+        let mut command = input
+            .split_whitespace()
+            .enumerate()
+            .map(|(i, token)| match token {
+                "t" if i > 0 => "true",
+                "f" if i > 0 => "false",
+                _ => token,
+            })
+            .collect::<Vec<&str>>();
         let mut command_name = "";
         if !command.is_empty() {
             command_name = command[0].trim();
         }
+        // A trailing draw mode only applies to the current command
+        let mut previous_draw_mode: Option<DrawMode> = None;
+        if DRAWING_COMMANDS.contains(&command_name) && command.len() > 1 {
+            if let Some(draw_mode) = DrawMode::from_name(command[command.len() - 1]) {
+                previous_draw_mode = Some(self.image.draw_mode);
+                self.image.draw_mode = draw_mode;
+                command.pop();
+            }
+        }
         let mut command_ok = true;
         match command_name {
             "help" | "h" => {
@@ -430,12 +1112,73 @@ impl Cli<'_> {
                 command_ok = false;
             }
             "dump" | "d" => {
-                let input_log_copy = input_log.to_owned();
-                for command in input_log_copy {
-                    println!("{};", command.trim());
+                const USAGE_MESSAGE: &str =
+                    "[--from-image] [--canonical] [--header] [path: file path]";
+                let mut from_image = false;
+                let mut canonical = false;
+                let mut header = false;
+                let mut path: Option<&str> = None;
+                let mut options_ok = true;
+                for (i, option) in command.iter().enumerate().skip(1) {
+                    match *option {
+                        "--from-image" => from_image = true,
+                        "--canonical" => canonical = true,
+                        "--header" => header = true,
+                        _ if path.is_none() && !option.starts_with("--") => {
+                            path = Some(verbatim_command[i])
+                        }
+                        _ => options_ok = false,
+                    }
+                }
+                if options_ok {
+                    self.dump(from_image, canonical, header, path);
+                } else {
+                    self.print_command_usage(command_name, USAGE_MESSAGE);
+                }
+                command_ok = false;
+            }
+            "let" => {
+                if command.len() == 1 {
+                    for (name, value) in self.interpreter.visible_variables() {
+                        println!("{name} = {value}");
+                    }
+                } else if let Err(message) = self.assign(&original_input.trim()[3..]) {
+                    eprintln!("{}", term::warning(&message));
+                    self.error = Some(message);
                 }
+                // Commands are logged with the values of their expressions
                 command_ok = false;
             }
+            "canvas" | "ca" => {
+                const USAGE_MESSAGE: &str = "[w: number] [h: number] [color: {t | f}]";
+                if command.len() == 4 {
+                    let w: Result<i32, _> = command[1].parse();
+                    let h: Result<i32, _> = command[2].parse();
+                    let c: Result<bool, _> = command[3].parse();
+                    if let (Ok(w), Ok(h), Ok(c)) = (w, h, c) {
+                        if !self.check_size(w, h) {
+                            command_ok = false;
+                        } else if self.canvas_fixed {
+                            println!("ignoring `canvas`: the canvas was set on the command line");
+                            command_ok = false;
+                        } else {
+                            self.image.grid = Image::new(w, h, c).grid;
+                            self.image.selection = None;
+                            self.image.clip = None;
+                            if self.log.is_empty() {
+                                self.canvas = (w, h, c);
+                                command_ok = false;
+                            }
+                        }
+                    } else {
+                        self.print_command_usage(command_name, USAGE_MESSAGE);
+                        command_ok = false;
+                    }
+                } else {
+                    self.print_command_usage(command_name, USAGE_MESSAGE);
+                    command_ok = false;
+                }
+            }
             "write" | "w" => {
                 const USAGE_MESSAGE: &str = "[x: number] [y: number] [color: {t | f}]";
                 if command.len() == 4 {
@@ -443,7 +1186,7 @@ impl Cli<'_> {
                     let y: Result<i32, _> = command[2].parse();
                     let c: Result<bool, _> = command[3].parse();
                     if let (Ok(x), Ok(y), Ok(c)) = (x, y, c) {
-                        self.image.write_pixel(x, y, c);
+                        self.image.plot_pixel(x, y, c);
                     } else {
                         self.print_command_usage(command_name, USAGE_MESSAGE);
                         command_ok = false;
@@ -476,7 +1219,11 @@ impl Cli<'_> {
                     let w: Result<i32, _> = command[1].parse();
                     let h: Result<i32, _> = command[2].parse();
                     if let (Ok(w), Ok(h)) = (w, h) {
-                        self.image.resize(w, h);
+                        if self.check_size(w, h) {
+                            self.image.resize(w, h);
+                        } else {
+                            command_ok = false;
+                        }
                     } else {
                         self.print_command_usage(command_name, USAGE_MESSAGE);
                         command_ok = false;
@@ -623,24 +1370,582 @@ impl Cli<'_> {
                     command_ok = false;
                 }
             }
+            "mode" | "m" => {
+                const USAGE_MESSAGE: &str = "[mode: {set | clear | toggle}]";
+                if command.len() == 1 {
+                    println!("draw mode: {}", self.image.draw_mode.name());
+                    command_ok = false;
+                } else if command.len() == 2 {
+                    if let Some(draw_mode) = DrawMode::from_name(command[1]) {
+                        self.image.draw_mode = draw_mode;
+                    } else {
+                        self.print_command_usage(command_name, USAGE_MESSAGE);
+                        command_ok = false;
+                    }
+                } else {
+                    self.print_command_usage(command_name, USAGE_MESSAGE);
+                    command_ok = false;
+                }
+            }
+            "pattern" | "p" => {
+                const USAGE_MESSAGE: &str =
+                    "[pattern: {solid | checkerboard | hatch | shade [level: 0-16] | load [path: file path]}]";
+                match command[1..] {
+                    [] => {
+                        let name = match &self.image.fill_pattern {
+                            Some(pattern) => pattern.name(),
+                            None => "solid",
+                        };
+                        println!("fill pattern: {name}");
+                        command_ok = false;
+                    }
+                    ["solid"] => self.image.fill_pattern = None,
+                    ["checkerboard"] => self.image.fill_pattern = Some(Pattern::checkerboard()),
+                    ["hatch"] => self.image.fill_pattern = Some(Pattern::hatch()),
+                    ["shade", level] => {
+                        if let Some(pattern) = level.parse().ok().and_then(Pattern::shade) {
+                            self.image.fill_pattern = Some(pattern);
+                        } else {
+                            self.print_command_usage(command_name, USAGE_MESSAGE);
+                            command_ok = false;
+                        }
+                    }
+                    ["load", _] => match Pattern::load(verbatim_command[2]) {
+                        Ok(pattern) => self.image.fill_pattern = Some(pattern),
+                        Err(message) => {
+                            eprintln!("{}", term::warning(&message));
+                            command_ok = false;
+                        }
+                    },
+                    _ => {
+                        self.print_command_usage(command_name, USAGE_MESSAGE);
+                        command_ok = false;
+                    }
+                }
+            }
+            "import" => {
+                const USAGE_MESSAGE: &str =
+                    "[path: file path] [algorithm: {threshold [level: 0-255] | otsu | bayer | floyd_steinberg | atkinson | stucki}] [serpentine]";
+                let mut arguments = command[1..].to_vec();
+                let serpentine = arguments.last() == Some(&"serpentine");
+                if serpentine {
+                    arguments.pop();
+                }
+                let algorithm =
+                    dither::Algorithm::from_arguments(arguments.get(1..).unwrap_or(&[]));
+                if let (false, Some(algorithm)) = (arguments.is_empty(), algorithm) {
+                    if let Err(message) =
+                        self.image
+                            .import(verbatim_command[1], algorithm, serpentine)
+                    {
+                        eprintln!("{}", term::warning(&message));
+                        command_ok = false;
+                    }
+                } else {
+                    self.print_command_usage(command_name, USAGE_MESSAGE);
+                    command_ok = false;
+                }
+            }
+            "clip" => {
+                const USAGE_MESSAGE: &str =
+                    "[x: number] [y: number] [w: number] [h: number] | none";
+                match command[1..] {
+                    [] => {
+                        match self.image.clip {
+                            Some(Rect { x, y, w, h }) => println!("clip: {x} {y} {w} {h}"),
+                            None => println!("clip: none"),
+                        }
+                        command_ok = false;
+                    }
+                    ["none"] => self.image.clip = None,
+                    [_, _, _, _] => match parse_rect(&command[1..]) {
+                        Ok(rect) => self.image.clip = Some(rect),
+                        Err(message) => {
+                            eprintln!("{}", term::warning(&message));
+                            command_ok = false;
+                        }
+                    },
+                    _ => {
+                        self.print_command_usage(command_name, USAGE_MESSAGE);
+                        command_ok = false;
+                    }
+                }
+            }
+            "select" => {
+                const USAGE_MESSAGE: &str =
+                    "[x: number] [y: number] [w: number] [h: number] | none";
+                match command[1..] {
+                    [] => {
+                        match self.image.selection {
+                            Some(Rect { x, y, w, h }) => println!("selection: {x} {y} {w} {h}"),
+                            None => println!("selection: none"),
+                        }
+                        command_ok = false;
+                    }
+                    ["none"] => self.image.selection = None,
+                    [_, _, _, _] => match parse_rect(&command[1..]) {
+                        Ok(rect) => self.image.selection = Some(rect),
+                        Err(message) => {
+                            eprintln!("{}", term::warning(&message));
+                            command_ok = false;
+                        }
+                    },
+                    _ => {
+                        self.print_command_usage(command_name, USAGE_MESSAGE);
+                        command_ok = false;
+                    }
+                }
+            }
+            "dilate" | "erode" | "open" | "close" => {
+                const USAGE_MESSAGE: &str =
+                    "[element: {square | cross | disk} [r: number] | load [path: file path]] [iterations: number]";
+                // A larger element would only cover the whole image more slowly
+                let max_radius = self.image.get_width().max(self.image.get_height()) as i32;
+                let element = match command[1..] {
+                    [] => Some(Ok(StructuringElement::square(1))),
+                    [kind @ ("square" | "cross" | "disk"), radius, ..] => {
+                        match radius.parse::<i32>() {
+                            Ok(radius) if radius >= 0 => {
+                                Some(StructuringElement::shape(kind, radius, max_radius))
+                            }
+                            _ => None,
+                        }
+                    }
+                    ["load", _, ..] => Some(StructuringElement::load(verbatim_command[2])),
+                    _ => None,
+                };
+                let iterations: Option<u32> = match command.len() {
+                    1 | 3 => Some(1),
+                    4 => command[3].parse().ok(),
+                    _ => None,
+                };
+                match (element, iterations) {
+                    (Some(Ok(element)), Some(iterations)) => match command_name {
+                        "dilate" => self.image.dilate(&element, iterations),
+                        "erode" => self.image.erode(&element, iterations),
+                        "open" => self.image.open(&element, iterations),
+                        _ => self.image.close(&element, iterations),
+                    },
+                    (Some(Err(message)), Some(_)) => {
+                        eprintln!("{}", term::warning(&message));
+                        command_ok = false;
+                    }
+                    _ => {
+                        self.print_command_usage(command_name, USAGE_MESSAGE);
+                        command_ok = false;
+                    }
+                }
+            }
+            "components" => {
+                const USAGE_MESSAGE: &str =
+                    "[color: {t | f}] [connectivity: {4 | 8}] [minimum area: number]";
+                let color: Option<bool> = command.get(1).and_then(|c| c.parse().ok());
+                let connectivity = match command.get(2) {
+                    Some(name) => Connectivity::from_name(name),
+                    None => Some(Connectivity::Eight),
+                };
+                let minimum_area: Option<Option<usize>> = match command.get(3) {
+                    Some(area) => area.parse().ok().map(Some),
+                    None => Some(None),
+                };
+                if let (true, Some(color), Some(connectivity), Some(minimum_area)) =
+                    (command.len() <= 4, color, connectivity, minimum_area)
+                {
+                    let components = self.image.label_components(color, connectivity);
+                    self.print_components(&components, color);
+                    match minimum_area {
+                        Some(minimum_area) => {
+                            let removed = self.image.despeckle(color, connectivity, minimum_area);
+                            println!(
+                                "removed {removed} components smaller than {minimum_area} pixels"
+                            );
+                        }
+                        None => command_ok = false,
+                    }
+                } else {
+                    self.print_command_usage(command_name, USAGE_MESSAGE);
+                    command_ok = false;
+                }
+            }
+            "outline" => {
+                const USAGE_MESSAGE: &str =
+                    "[color: {t | f}] [boundary: {inner | outer}] [connectivity: {4 | 8}]";
+                let color: Option<bool> = command.get(1).and_then(|c| c.parse().ok());
+                let boundary = match command.get(2) {
+                    Some(name) => contours::Boundary::from_name(name),
+                    None => Some(contours::Boundary::Inner),
+                };
+                let connectivity = match command.get(3) {
+                    Some(name) => Connectivity::from_name(name),
+                    None => Some(Connectivity::Eight),
+                };
+                if let (true, Some(color), Some(boundary), Some(connectivity)) =
+                    (command.len() <= 4, color, boundary, connectivity)
+                {
+                    self.image.outline(color, boundary, connectivity);
+                } else {
+                    self.print_command_usage(command_name, USAGE_MESSAGE);
+                    command_ok = false;
+                }
+            }
+            "contours" => {
+                const USAGE_MESSAGE: &str = "[color: {t | f}]";
+                let color: Option<bool> = command.get(1).and_then(|c| c.parse().ok());
+                if let (2, Some(color)) = (command.len(), color) {
+                    for (i, contour) in self.image.trace_contours(color).iter().enumerate() {
+                        let points = contour
+                            .iter()
+                            .map(|(x, y)| format!("({x}, {y})"))
+                            .collect::<Vec<String>>();
+                        println!("{}: {}", i + 1, points.join(" "));
+                    }
+                } else {
+                    self.print_command_usage(command_name, USAGE_MESSAGE);
+                }
+                command_ok = false;
+            }
+            "export" => {
+                const USAGE_MESSAGE: &str = "{svg [path: file path] [mode: {pixel | traced [tolerance: number]}] | pbm [path: file path]}";
+                let mode = command.get(3..).and_then(svg::Mode::from_arguments);
+                if let (Some(&"svg"), Some(mode)) = (command.get(1), mode) {
+                    if let Err(message) = self.image.export_svg(verbatim_command[2], mode) {
+                        eprintln!("{}", term::warning(&message));
+                    }
+                } else if let (3, Some(&"pbm")) = (command.len(), command.get(1)) {
+                    if let Err(message) = pnm::write_pbm(verbatim_command[2], &self.image.grid) {
+                        eprintln!("{}", term::warning(&message));
+                    }
+                } else {
+                    self.print_command_usage(command_name, USAGE_MESSAGE);
+                }
+                command_ok = false;
+            }
+            "edit" => {
+                if let Err(message) = self.edit() {
+                    eprintln!("{}", term::warning(&message));
+                }
+                command_ok = false;
+            }
+            "view" => {
+                const USAGE_MESSAGE: &str = "[mode: {blocks | half | quadrant | braille}] | fit";
+                match command[1..] {
+                    [] => println!("view: {}", self.render_options.mode.name()),
+                    ["fit"] => self.fit(),
+                    [mode] => match RenderMode::from_name(mode) {
+                        Some(mode) => self.render_options.mode = mode,
+                        None => self.print_command_usage(command_name, USAGE_MESSAGE),
+                    },
+                    _ => self.print_command_usage(command_name, USAGE_MESSAGE),
+                }
+                command_ok = false;
+            }
+            "config" => {
+                const USAGE_MESSAGE: &str = "show";
+                match command[1..] {
+                    ["show"] => print!("{}", self.config.to_toml()),
+                    _ => self.print_command_usage(command_name, USAGE_MESSAGE),
+                }
+                command_ok = false;
+            }
+            "theme" => {
+                const USAGE_MESSAGE: &str = "[preset: {default | amber | phosphor | paper}] | glyphs [on: character] [off: character] | {foreground | background} [color] | frame [style: {ascii | single | rounded | double | heavy}]";
+                // A single character, or `space`
+                let glyph = |name: &str| -> Option<char> {
+                    let name = name.trim_matches('"');
+                    let mut characters = name.chars();
+                    match (characters.next(), characters.next()) {
+                        _ if name.eq_ignore_ascii_case("space") => Some(' '),
+                        (Some(character), None) => Some(character),
+                        _ => None,
+                    }
+                };
+                let color = |name: &str| -> Option<Option<Color>> {
+                    if name == "none" {
+                        Some(None)
+                    } else {
+                        Color::from_name(name).map(Some)
+                    }
+                };
+                let theme = &mut self.render_options.theme;
+                match command[1..] {
+                    [] => println!("{}", theme.describe()),
+                    ["glyphs", _, _] => {
+                        match (glyph(verbatim_command[2]), glyph(verbatim_command[3])) {
+                            (Some(on), Some(off)) => (theme.on, theme.off) = (on, off),
+                            _ => self.print_command_usage(command_name, USAGE_MESSAGE),
+                        }
+                    }
+                    ["foreground", name] => match color(name) {
+                        Some(color) => theme.foreground = color,
+                        None => self.print_command_usage(command_name, USAGE_MESSAGE),
+                    },
+                    ["background", name] => match color(name) {
+                        Some(color) => theme.background = color,
+                        None => self.print_command_usage(command_name, USAGE_MESSAGE),
+                    },
+                    ["frame", style] => match FrameStyle::from_name(style) {
+                        Some(style) => theme.frame = style,
+                        None => self.print_command_usage(command_name, USAGE_MESSAGE),
+                    },
+                    [preset] => match Theme::from_preset(preset) {
+                        Some(preset) => *theme = preset,
+                        None => self.print_command_usage(command_name, USAGE_MESSAGE),
+                    },
+                    _ => self.print_command_usage(command_name, USAGE_MESSAGE),
+                }
+                command_ok = false;
+            }
+            "rulers" => {
+                const USAGE_MESSAGE: &str = "[shown: {t | f}]";
+                let shown: Option<bool> = command.get(1).and_then(|c| c.parse().ok());
+                if let (2, Some(shown)) = (command.len(), shown) {
+                    self.render_options.rulers = shown;
+                } else {
+                    self.print_command_usage(command_name, USAGE_MESSAGE);
+                }
+                command_ok = false;
+            }
+            "grid" => {
+                const USAGE_MESSAGE: &str = "[spacing: number] | none";
+                match command[1..] {
+                    ["none"] => self.render_options.grid = None,
+                    [spacing] => match spacing.parse() {
+                        Ok(spacing) if spacing > 0 => self.render_options.grid = Some(spacing),
+                        _ => self.print_command_usage(command_name, USAGE_MESSAGE),
+                    },
+                    _ => self.print_command_usage(command_name, USAGE_MESSAGE),
+                }
+                command_ok = false;
+            }
+            "crosshair" => {
+                const USAGE_MESSAGE: &str = "[x: number] [y: number] | none";
+                match command[1..] {
+                    ["none"] => self.render_options.crosshair = None,
+                    [x, y] => match (x.parse(), y.parse()) {
+                        (Ok(x), Ok(y)) => self.render_options.crosshair = Some((x, y)),
+                        _ => self.print_command_usage(command_name, USAGE_MESSAGE),
+                    },
+                    _ => self.print_command_usage(command_name, USAGE_MESSAGE),
+                }
+                command_ok = false;
+            }
+            "zoom" => {
+                const USAGE_MESSAGE: &str = "[level: number] | in | out";
+                match command[1..] {
+                    [] => println!("zoom: {}", self.viewport.ratio()),
+                    ["in"] => self.set_zoom(self.viewport.zoom + 1),
+                    ["out"] => self.set_zoom(self.viewport.zoom - 1),
+                    [level] => match level.parse::<i32>() {
+                        Ok(level) if level.abs() <= viewport::MAX_ZOOM => self.set_zoom(level),
+                        _ => self.print_command_usage(command_name, USAGE_MESSAGE),
+                    },
+                    _ => self.print_command_usage(command_name, USAGE_MESSAGE),
+                }
+                command_ok = false;
+            }
+            "pan" | "scroll" => {
+                const USAGE_MESSAGE: &str = "[x: number] [y: number]";
+                let x: Option<i64> = command.get(1).and_then(|x| x.parse().ok());
+                let y: Option<i64> = command.get(2).and_then(|y| y.parse().ok());
+                if let (3, Some(x), Some(y)) = (command.len(), x, y) {
+                    if command_name == "pan" {
+                        self.pan(x, y);
+                    } else {
+                        let area = self.visible_area();
+                        self.pan(area.x as i64 + x, area.y as i64 + y);
+                    }
+                } else {
+                    self.print_command_usage(command_name, USAGE_MESSAGE);
+                }
+                command_ok = false;
+            }
+            "hash" => {
+                println!("{:016x}", self.image.hash());
+                command_ok = false;
+            }
+            "assert_pixel" => {
+                const USAGE_MESSAGE: &str = "[x: number] [y: number] [color: {t | f}]";
+                let x: Option<i32> = command.get(1).and_then(|x| x.parse().ok());
+                let y: Option<i32> = command.get(2).and_then(|y| y.parse().ok());
+                let c: Option<bool> = command.get(3).and_then(|c| c.parse().ok());
+                if let (4, Some(x), Some(y), Some(c)) = (command.len(), x, y, c) {
+                    let is_inside = x >= 0
+                        && y >= 0
+                        && (x as usize) < self.image.get_width()
+                        && (y as usize) < self.image.get_height();
+                    if !is_inside {
+                        self.failure = Some(format!("({x}, {y}) is outside the image"));
+                    } else if self.image.read_pixel(x, y) != c {
+                        self.failure = Some(format!(
+                            "expected ({x}, {y}) to be {c}; {}",
+                            self.image.neighbourhood(x, y, c)
+                        ));
+                    }
+                } else {
+                    self.print_command_usage(command_name, USAGE_MESSAGE);
+                }
+                command_ok = false;
+            }
+            "assert_count" => {
+                const USAGE_MESSAGE: &str = "[color: {t | f}] [count: number]";
+                let c: Option<bool> = command.get(1).and_then(|c| c.parse().ok());
+                let n: Option<usize> = command.get(2).and_then(|n| n.parse().ok());
+                if let (3, Some(c), Some(n)) = (command.len(), c, n) {
+                    let count = self.image.count(c);
+                    if count != n {
+                        let mut message = format!("expected {n} pixels to be {c}, not {count}");
+                        if let Some(Rect { x, y, w, h }) = self.image.bounds(c) {
+                            message += &format!(
+                                ", found between ({x}, {y}) and ({}, {})",
+                                x + w - 1,
+                                y + h - 1
+                            );
+                        }
+                        self.failure = Some(message);
+                    }
+                } else {
+                    self.print_command_usage(command_name, USAGE_MESSAGE);
+                }
+                command_ok = false;
+            }
+            "assert_equals" => {
+                const USAGE_MESSAGE: &str = "[path: file path]";
+                if command.len() == 2 {
+                    let path = self.resolve_path(verbatim_command[1]);
+                    match pnm::read_pbm(&path.to_string_lossy()) {
+                        Ok(expected) => {
+                            if let Some(diff) = self.image.diff(&expected) {
+                                self.failure = Some(format!(
+                                    "the image doesn't match '{}': {diff}",
+                                    verbatim_command[1]
+                                ));
+                            }
+                        }
+                        Err(message) => self.failure = Some(message),
+                    }
+                } else {
+                    self.print_command_usage(command_name, USAGE_MESSAGE);
+                }
+                command_ok = false;
+            }
+            "assert_hash" => {
+                const USAGE_MESSAGE: &str = "[hash: hexadecimal number]";
+                let expected: Option<u64> = command.get(1).and_then(|hash| {
+                    let hash = hash.strip_prefix("0x").unwrap_or(hash);
+                    u64::from_str_radix(hash, 16).ok()
+                });
+                if let (2, Some(expected)) = (command.len(), expected) {
+                    let hash = self.image.hash();
+                    if hash != expected {
+                        self.failure =
+                            Some(format!("expected hash {expected:016x}, not {hash:016x}"));
+                    }
+                } else {
+                    self.print_command_usage(command_name, USAGE_MESSAGE);
+                }
+                command_ok = false;
+            }
             "invert" | "i" => self.image.invert(),
             "quit" | "q" => std::process::exit(0),
             "" => command_ok = false,
             _ => {
-                eprintln!("unrecognized command '{}'", command_name);
+                let message = format!("unrecognized command '{command_name}'");
+                eprintln!("{message}");
+                self.error = Some(message);
                 command_ok = false;
             }
         }
+        if let Some(draw_mode) = previous_draw_mode {
+            self.image.draw_mode = draw_mode;
+        }
         if command_ok {
-            input_log.push(original_input);
+            self.log.push(LogEntry::new(&original_input));
         }
     }
-    fn parse_script(&mut self, script_text: String) {
+    /// Runs the script at `path`, returning whether it succeeded
+    fn parse_script(&mut self, path: &std::path::Path) -> bool {
         println!("Running script…");
-        for command in script_text.split(";") {
-            self.parse_command(command.to_owned(), &mut vec![]);
+        let result = self.run_file(path);
+        if let Err(message) = &result {
+            eprintln!("{}", term::error(&format!("script error: {message}")));
         }
         println!("Done running script");
+        result.is_ok()
+    }
+    /// Parses and executes typed statements, where the aliases of the configuration apply
+    fn run(&mut self, text: &str) -> Result<(), String> {
+        let mut statements = script::parse_script(text)?;
+        script::expand_aliases(&mut statements, &self.config.aliases);
+        self.execute(&statements)
+    }
+    /// Prints the session log, or a script drawing the image, or writes it to `path`
+    fn dump(&self, from_image: bool, canonical: bool, header: bool, path: Option<&str>) {
+        let script = self.dump_script(from_image, canonical, header);
+        match path {
+            Some(path) => {
+                if let Err(e) = std::fs::write(path, script) {
+                    eprintln!(
+                        "{}",
+                        term::warning(&format!("failed to write '{path}': {e}"))
+                    );
+                }
+            }
+            None => print!("{script}"),
+        }
+    }
+    /// Returns the session log, or a script drawing the image, with a header if asked
+    fn dump_script(&self, from_image: bool, canonical: bool, header: bool) -> String {
+        let commands: Vec<String> = if from_image {
+            let script = self.image.to_script();
+            if !self.verify_script(&script) {
+                eprintln!("{}", term::error("the script doesn't reproduce the image"));
+            }
+            // The canvas only gives the size of the image, which `clear` then fills
+            let (w, h) = (self.image.get_width(), self.image.get_height());
+            let mut commands = vec![format!("canvas {w} {h} f")];
+            commands.extend(script);
+            commands
+        } else {
+            let (w, h, c) = self.canvas;
+            let mut commands = vec![format!("canvas {w} {h} {}", if c { "t" } else { "f" })];
+            for entry in &self.log {
+                if canonical {
+                    commands.push(entry.canonical.to_owned());
+                } else {
+                    commands.push(entry.raw.to_owned());
+                }
+            }
+            commands
+        };
+        let mut script = String::new();
+        if header {
+            script += &format!(
+                "# ipcli script, {}x{} image\n",
+                self.image.get_width(),
+                self.image.get_height()
+            );
+        }
+        for command in commands {
+            script += &format!("{command};\n");
+        }
+        script
+    }
+    fn print_components(&self, components: &[components::Component], color: bool) {
+        println!("{} components of color {color}", components.len());
+        if components.is_empty() {
+            return;
+        }
+        println!("     # |   area | bounding box (x y w h) | centroid");
+        for (i, component) in components.iter().enumerate() {
+            let Rect { x, y, w, h } = component.bounding_box;
+            let (centroid_x, centroid_y) = component.centroid;
+            println!(
+                "{:>6} | {:>6} | {:<22} | ({centroid_x:.1}, {centroid_y:.1})",
+                i + 1,
+                component.area(),
+                format!("{x} {y} {w} {h}"),
+            );
+        }
     }
     fn print_welcome_message(&self) {
         println!("Welcome to ipcli. Type 'help' for help. Type 'quit' to quit.");
@@ -650,18 +1955,35 @@ impl Cli<'_> {
     Manipulate one-bit bitmap graphics from the command-line.
 
 \x1b[1mUSAGE\x1b[0m
-    ipcli [w: number] [h: number] [color: {t | f}]
-        Creates a new image of the specified dimensions and color.
-    ipcli -s [path: file path]
-        Generates the image from the script at `path` and start the IPCLI.
+    ipcli [w: number] [h: number] [color: {t | f}] [-v [mode]] [--config [path: file path]]
+        Creates a new image of the specified dimensions and color. `-v` (`--view`) sets the render mode. `--config` reads the settings from the file at `path` instead of the configuration file.
+    ipcli [w: number] [h: number] [color: {t | f}] [-v [mode]] [--config [path: file path]] -s [path: file path] [-b] [-o [path]] [-I [directory]]... [--set [name]=[value]]... [-- [values]...]
+        Generates the image from the script at `path` and start the IPCLI. The dimensions and color are optional and take precedence over the script's `canvas` directive. `-o` writes the resulting image to a PBM file. `-b` (`--batch`) exits after the script, with status 1 if it failed; an invalid command or expression then fails it too. `-I` adds a directory to search for included scripts. `--set` gives a value to a parameter of the script, and the values after `--` are given to its parameters in order.
     
 \x1b[1mCOMMANDS\x1b[0m
     help               | h: Prints this message;
+    canvas [w] [h] [c] | ca: Replaces the image with a new `w` * `h` image of color `c`, up to 10000 * 10000;
     write [x] [y] [c]  | w: Sets the pixel at (x, y) to color `c`;
     fill [x] [y] [c]   | f: Flood fills from (x, y) with color `c`;
-    resize [w] [h]     | r: Resizes the image to `w` * `h`;
+    resize [w] [h]     | r: Resizes the image to `w` * `h`, up to 10000 * 10000;
     clear [c]          | c: Fills the image with color `c`;
     invert             | i: Inverts the image;
+    mode [mode]        | m: Sets the draw mode used by drawing commands;
+    pattern [pattern]  | p: Sets the pattern used by filled shapes and `fill`;
+    edit                 : Opens a full-screen editor, where arrow keys move a cursor, space toggles pixels, `t` and `f` set them and mouse buttons paint; changes are logged as `write` commands;
+    view [mode]          : Sets how the image is rendered in the terminal, or shows the whole image with `view fit`;
+    zoom [level]         : Shows pixels 2^`level` times larger, from -4 to 4, or zooms with `zoom in` and `zoom out`;
+    pan [x] [y]          : Shows the image from (x, y) when it doesn't fit in the terminal, below which an overview of the whole image shows the visible part darker;
+    scroll [dx] [dy]     : Moves the visible part of the image by `dx` and `dy` pixels;
+    rulers [c]           : Shows coordinates along the top and left of the image if `c` is `t`;
+    grid [n]             : Shows a dotted grid every `n` pixels on empty pixels, or hides it with `grid none`;
+    crosshair [x] [y]    : Marks the pixel at (x, y) with a cross on empty pixels, or hides it with `crosshair none`;
+    config show          : Prints the settings read from the configuration file;
+    theme [preset]       : Sets how pixels and the frame look to the `default`, `amber`, `phosphor` or `paper` theme, or prints the current one;
+        glyphs [on] [off]  : draws `t` and `f` pixels with the characters `on` and `off`, twice each, in the `blocks` render mode (`space` for a space);
+        foreground [color] : colors `t` pixels, or not with `none`;
+        background [color] : colors `f` pixels, or not with `none`;
+        frame [style]      : draws the frame with `ascii`, `single`, `rounded`, `double` or `heavy` lines.
     quit               | q: Exits the program;
     ---
     draw_rectangle [x] [y] [w] [h] [c]           | dr: Draws a `w` * `h` rectangle of color `c` at (x, y);
@@ -672,28 +1994,448 @@ impl Cli<'_> {
     draw_rectangle_outline [x] [y] [w] [h] [c] | dro: Draws the outline of a `w` * `h` rectangle at (x, y) witcolor `c`;
     draw_circle_outline [x] [y] [r] [c]        | dco: Draws the outline of a circle of radius `r` with centre (x,  with color `c`;
     ---
-    dump | d: Dumps all executed commands as a script to stdout.
+    dump [options] [path] | d: Dumps all executed commands, including the ones from scripts, as a script to stdout or to the file at `path`, starting with the initial `canvas`;
+        --canonical : uses full command and draw mode names and `t` or `f` colors;
+        --header    : starts with a comment giving the image size;
+        --from-image: dumps a script drawing the current image instead: a `canvas` of its size, then `clear` with its dominant color, `draw_rectangle` and `write` commands, checking that they reproduce the image over any image of that size.
+    let [name] = [expression]: Sets the variable `name` to the value of `expression`, or lists the variables if there are no arguments;
+    ---
+    hash                    : Prints a hash of the size and pixels of the image;
+    assert_pixel [x] [y] [c]: Fails the script unless the pixel at (x, y) has color `c`, showing the pixels around it;
+    assert_count [c] [n]    : Fails the script unless `n` pixels have color `c`, showing the rectangle holding them;
+    assert_equals [path]    : Fails the script unless the image equals the PBM file at `path`, relative to the script, showing the differing pixels;
+    assert_hash [hash]      : Fails the script unless the image has the hexadecimal hash `hash`, as printed by `hash`;
+    ---
+    clip [x] [y] [w] [h]   | clip none  : Restricts drawing commands to a `w` * `h` rectangle at (x, y);
+    select [x] [y] [w] [h] | select none: Restricts morphological operations to a `w` * `h` rectangle at (x, y);
+    dilate [e] [n]         : Grows `t` shapes by structuring element `e`, `n` times;
+    erode [e] [n]          : Shrinks `t` shapes by structuring element `e`, `n` times;
+    open [e] [n]           : Erodes then dilates, removing specks smaller than `e`;
+    close [e] [n]          : Dilates then erodes, filling holes smaller than `e`;
+    components [c] [k] [a] : Lists the `k`-connected regions of color `c`; if `a` is given, removes the ones smaller than `a` pixels;
+    outline [c] [b] [k]    : Replaces the regions of color `c` with their `inner` (default) or `outer` boundary `b`, using `k` neighbours;
+    contours [c]           : Prints the boundary pixels of every region of color `c`, in clockwise order;
+    ---
+    import [path] [algorithm] [serpentine]: Replaces the image with the PBM, PGM or PPM file at `path`, converted with `algorithm` (`threshold [level]`, `otsu`, `bayer`, `floyd_steinberg` (default), `atkinson` or `stucki`); `serpentine` alternates the scanning direction of error diffusion. Dark pixels become `t`.
+    export svg [path] [mode]: Writes the `t` pixels as black shapes to an SVG file, made of rectangles (`pixel`, default) or of contours (`traced`), optionally simplified within a `tolerance` in pixels (`traced [tolerance]`);
+    export pbm [path]       : Writes the image to a plain PBM file, `t` pixels being black.
 
 \x1b[1mABBREVIATIONS\x1b[0m
-    x: x-coordinate (must be positive or zero);
-    y: y-coordinate (must be positive or zero);
+    x: x-coordinate (may be negative, shapes being clipped to the image);
+    y: y-coordinate (may be negative, shapes being clipped to the image);
     w: width        (must be positive or zero);
     h: height       (must be positive or zero);
     r: radius       (must be positive or zero);
     c: color        (must be either `t` or `f`);
+    e: structuring element (`square [r]`, `cross [r]`, `disk [r]` or `load [path]` for a PBM bitmap centred on its middle pixel; `square 1` by default);
+    n: number of iterations (1 by default);
+    k: connectivity (`4` or `8`; `8` by default);
     ---
     t: shorthand for `true`;
     f: shorthand for `false`.
+
+\x1b[1mRENDER MODES\x1b[0m
+    blocks  : two characters per pixel (default);
+    half    : half blocks, 1 * 2 pixels per character;
+    quadrant: quadrant blocks, 2 * 2 pixels per character;
+    braille : Braille patterns, 2 * 4 pixels per character.
+
+\x1b[1mCOLORS\x1b[0m
+    Colors are named (`black`, `red`, `green`, `yellow`, `blue`, `magenta`, `cyan`, `white`, or the same prefixed with `bright_`), numbered from 0 to 255, or given as `rrggbb` in hexadecimal (like `ffb000`, or `\"#ffb000\"` quoted). They are approximated when the terminal shows fewer colors, depending on the `COLORTERM` and `TERM` environment variables, and left out when the `NO_COLOR` environment variable is set or the output isn't a terminal.
+
+\x1b[1mCONFIGURATION\x1b[0m
+    Settings are read at startup from `$XDG_CONFIG_HOME/ipcli/config.toml`, or else `~/.config/ipcli/config.toml`, written in a subset of TOML (strings, integers, booleans and arrays of them):
+    prompt = \"ipcli> \"      : the prompt;
+    history_size = 1000     : the number of lines kept in `~/.ipcli_history`;
+    [canvas]                : the initial image, when no dimensions are given:
+        width = 10, height = 10, color = false, up to 10000 * 10000;
+    [view]
+        mode = \"blocks\"     : the render mode, which `-v` takes precedence over;
+    [theme]                 : the theme, as set by `theme`:
+        preset = \"amber\", glyphs = [\"█\", \" \"], foreground = \"#ffb000\", background = \"black\", frame = \"single\";
+    [aliases]               : names other than commands and keywords standing for the start of a typed command, not in scripts, which is logged instead:
+        sq = \"draw_rectangle\".
+
+\x1b[1mLINE EDITING\x1b[0m
+    left, right   : moves the cursor (also home and end, or ctrl-a and ctrl-e);
+    up, down      : recalls previous lines, kept in `~/.ipcli_history` across sessions;
+    tab           : completes command names and aliases, then file paths;
+    ctrl-r        : searches previous lines for the text typed next;
+    ctrl-u, ctrl-k: deletes the text before or after the cursor (ctrl-w: the previous word);
+    ctrl-c        : abandons the line (ctrl-d on an empty line quits).
+    Lines are read without editing when the input isn't a terminal.
+
+\x1b[1mDRAW MODES\x1b[0m
+    set   : pixels take the color `c` (default);
+    clear : pixels covered by `t` are cleared;
+    toggle: pixels covered by `t` are inverted (also `xor`).
+    Drawing commands (`write`, `fill` and `draw_*`) accept a draw mode as an optional last argument, which only applies to that command.
+
+\x1b[1mFILL PATTERNS\x1b[0m
+    solid            : no pattern (default);
+    checkerboard     : alternating pixels;
+    hatch            : diagonal lines;
+    shade [level]    : Bayer dithered shade from 0 (empty) to 16 (solid);
+    load [path]      : pattern loaded from a PBM bitmap.
+    Patterned shapes use color `c` where the pattern is set and the opposite color elsewhere.
     
 \x1b[1mSCRIPTING\x1b[0m
-    The IPCLI supports basic scripting. Scripts are plain text files (the `.ipcli` extension is recommended) containing a list of commands to execute, separated by semicolons. See the documentation on the `dump` command and the `-s` option. Sample scripts are available in the source repository.";
-        println!("{}", HELP_TEXT);
+    The IPCLI supports basic scripting. Scripts are plain text files (the `.ipcli` extension is recommended) containing a list of commands to execute, separated by semicolons. Text from `#` to the end of a line is a comment. Numbers can be replaced with integer expressions using variables, `+`, `-`, `*`, `/`, `%`, parentheses, `min(...)`, `max(...)`, `width` and `height`, like `dr (width / 2) 0 x+1 5 t`; spaces are only allowed within parentheses. Commands are dumped with the values of their expressions. Blocks of statements can be repeated with `repeat [n] { ... }` or `for [name] in [start]..[end] step [step] { ... }` (`end` excluded, `step` 1 by default), and run conditionally with `if [condition] { ... } else { ... }`, where conditions may use `==`, `!=`, `<`, `<=`, `>`, `>=`, `&&`, `||` and `!` and are true when not 0. Procedures are defined with `def [name]([parameters]) { ... }` and called like commands, with numbers or colors as arguments, a color being 1 for `t` and 0 for `f`; their parameters and variables are local. Expressions given as colors are `t` when not 0. `include \"[path]\"` runs another script, looked up next to the current script (or in the working directory), then in the `-I` directories, then in the directories listed in the `IPCLI_PATH` environment variable. `param [name]` declares a parameter given on the command line, and `param [name] = [expression]` one with a default value; the script doesn't run if a parameter without a default value is missing. A script starting with `canvas [w] [h] [c]` sets the initial image. See the documentation on the `dump` command and the `-s` option. Sample scripts are available in the source repository.";
+        println!("{}", term::styled(HELP_TEXT));
     }
-    fn print_command_usage(&self, command_name: &str, usage_message: &str) {
-        const RED: &str = "\x1b[31m";
-        const RESET: &str = "\x1b[0m";
+    fn print_command_usage(&mut self, command_name: &str, usage_message: &str) {
+        let message = format!("{command_name}: invalid options");
         eprintln!(
-            "{RED}{command_name}: invalid options\n{RESET}usage: {command_name} {usage_message}"
+            "{}\nusage: {command_name} {usage_message}",
+            term::error(&message)
         );
+        self.error = Some(message);
+    }
+    /// Warns about an image size out of bounds, which fails the script, and returns whether
+    /// `w` * `h` is allowed
+    fn check_size(&mut self, w: i32, h: i32) -> bool {
+        if is_valid_size(w, h) {
+            return true;
+        }
+        let message = format!(
+            "width and height must be between 1 and {}",
+            config::MAX_CANVAS_SIZE
+        );
+        eprintln!("{}", term::warning(&message));
+        self.error = Some(message);
+        false
+    }
+}
+
+/// Whether `w` * `h` is an allowed image size, so that huge images don't exhaust the memory
+fn is_valid_size(w: i32, h: i32) -> bool {
+    let range = 1..=config::MAX_CANVAS_SIZE;
+    range.contains(&(w as i64)) && range.contains(&(h as i64))
+}
+
+#[cfg(test)]
+impl Image {
+    /// Builds an image from rows of `#` (`t`) and `.` (`f`) pixels
+    pub fn from_rows(rows: &[&str]) -> Image {
+        let mut image = Image::new(rows[0].len() as i32, rows.len() as i32, false);
+        for (line, row) in image.grid.iter_mut().zip(rows) {
+            *line = row.chars().map(|pixel| pixel == '#').collect();
+        }
+        image
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cli, Image, LogEntry, Rect};
+    use std::collections::BTreeSet;
+
+    const RADII: std::ops::RangeInclusive<i32> = 0..=24;
+    /// An area holding every tested circle
+    const EVERYWHERE: Rect = Rect {
+        x: -100,
+        y: -100,
+        w: 200,
+        h: 200,
+    };
+
+    fn set_pixels(image: &Image) -> BTreeSet<(i32, i32)> {
+        let mut pixels = BTreeSet::new();
+        for (y, row) in image.grid.iter().enumerate() {
+            for (x, &pixel) in row.iter().enumerate() {
+                if pixel {
+                    pixels.insert((x as i32, y as i32));
+                }
+            }
+        }
+        pixels
+    }
+
+    #[test]
+    fn circle_outline_has_eight_fold_symmetry() {
+        for radius in RADII {
+            for (xc, yc) in [(0, 0), (7, -3), (-11, 20)] {
+                let pixels: BTreeSet<(i32, i32)> = Image::circle_pixels(xc, yc, radius, EVERYWHERE)
+                    .into_iter()
+                    .collect();
+                for &(x, y) in &pixels {
+                    let (dx, dy) = (x - xc, y - yc);
+                    for (mx, my) in [(-dx, dy), (dx, -dy), (dy, dx), (-dy, -dx)] {
+                        assert!(
+                            pixels.contains(&(xc + mx, yc + my)),
+                            "radius {radius}: ({x}, {y}) has no mirror ({}, {})",
+                            xc + mx,
+                            yc + my
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn circle_outline_stays_within_half_a_pixel_of_the_radius() {
+        for radius in RADII {
+            for (x, y) in Image::circle_pixels(0, 0, radius, EVERYWHERE) {
+                let distance = ((x * x + y * y) as f32).sqrt();
+                assert!(
+                    (distance - radius as f32).abs() <= 0.5 + f32::EPSILON * 64.0,
+                    "radius {radius}: ({x}, {y}) is {distance} from the centre"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn filled_circle_is_the_interior_of_its_outline() {
+        for radius in RADII {
+            let size = 2 * radius + 1;
+            let mut disc = Image::new(size, size, false);
+            let mut outline = Image::new(size, size, false);
+            disc.draw_circle(radius, radius, radius, true);
+            outline.draw_circle_outline(radius, radius, radius, true);
+            let disc_pixels = set_pixels(&disc);
+            let outline_pixels = set_pixels(&outline);
+            assert!(
+                outline_pixels.is_subset(&disc_pixels),
+                "radius {radius}: the outline isn't covered by the disc"
+            );
+            // Every row of the disc runs between the two ends of the outline on that row
+            for y in 0..size {
+                let ends: Vec<i32> = outline_pixels
+                    .iter()
+                    .filter(|&&(_, py)| py == y)
+                    .map(|&(x, _)| x)
+                    .collect();
+                let row: BTreeSet<i32> = disc_pixels
+                    .iter()
+                    .filter(|&&(_, py)| py == y)
+                    .map(|&(x, _)| x)
+                    .collect();
+                let expected: BTreeSet<i32> =
+                    (*ends.iter().min().unwrap()..=*ends.iter().max().unwrap()).collect();
+                assert_eq!(row, expected, "radius {radius}, row {y}");
+            }
+        }
+    }
+
+    #[test]
+    fn circles_near_or_outside_the_edges_are_clipped() {
+        const SIZE: i32 = 9;
+        // A larger image with the small one in its middle, where no circle is clipped
+        const MARGIN: i32 = 40;
+        for radius in 0..=12 {
+            for xc in [-radius - 1, -3, 0, 1, SIZE / 2, SIZE - 1, SIZE + 2] {
+                for yc in [-2, 0, SIZE - 1, SIZE + radius] {
+                    for filled in [false, true] {
+                        let mut small = Image::new(SIZE, SIZE, false);
+                        let mut large = Image::new(SIZE + 2 * MARGIN, SIZE + 2 * MARGIN, false);
+                        if filled {
+                            small.draw_circle(xc, yc, radius, true);
+                            large.draw_circle(xc + MARGIN, yc + MARGIN, radius, true);
+                        } else {
+                            small.draw_circle_outline(xc, yc, radius, true);
+                            large.draw_circle_outline(xc + MARGIN, yc + MARGIN, radius, true);
+                        }
+                        let cropped: BTreeSet<(i32, i32)> = set_pixels(&large)
+                            .into_iter()
+                            .map(|(x, y)| (x - MARGIN, y - MARGIN))
+                            .filter(|&(x, y)| small.is_inside(x, y))
+                            .collect();
+                        assert_eq!(
+                            set_pixels(&small),
+                            cropped,
+                            "radius {radius}, centre ({xc}, {yc}), filled {filled}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn circle_of_radius_zero_is_one_pixel() {
+        for filled in [false, true] {
+            let mut image = Image::new(3, 3, false);
+            if filled {
+                image.draw_circle(1, 1, 0, true);
+            } else {
+                image.draw_circle_outline(1, 1, 0, true);
+            }
+            assert_eq!(set_pixels(&image), BTreeSet::from([(1, 1)]));
+        }
+    }
+
+    #[test]
+    fn circle_outline_is_the_one_of_the_midpoint_algorithm() {
+        for radius in 0..=200 {
+            let mut expected: Vec<(i32, i32)> = Vec::new();
+            let (mut x, mut y) = (radius, 0);
+            let mut error = 1 - radius;
+            while x >= y {
+                for (dx, dy) in [(x, y), (y, x)] {
+                    expected.extend([(dx, dy), (-dx, dy), (dx, -dy), (-dx, -dy)]);
+                }
+                y += 1;
+                if error < 0 {
+                    error += 2 * y + 1;
+                } else {
+                    x -= 1;
+                    error += 2 * (y - x) + 1;
+                }
+            }
+            expected.sort_unstable();
+            expected.dedup();
+            let area = Rect {
+                x: -radius,
+                y: -radius,
+                w: 2 * radius + 1,
+                h: 2 * radius + 1,
+            };
+            assert_eq!(
+                Image::circle_pixels(0, 0, radius, area),
+                expected,
+                "radius {radius}"
+            );
+        }
+    }
+
+    #[test]
+    fn lines_are_the_same_when_clipped() {
+        const SIZE: Rect = Rect {
+            x: 0,
+            y: 0,
+            w: 10,
+            h: 6,
+        };
+        let ends: Vec<(i32, i32)> = (-8..18)
+            .step_by(3)
+            .flat_map(|x| (-5..12).step_by(2).map(move |y| (x, y)))
+            .collect();
+        for &(x1, y1) in &ends {
+            for &(x2, y2) in &ends {
+                let whole = Image::line_pixels(x1, y1, x2, y2, EVERYWHERE);
+                assert!(whole.contains(&(x1, y1)) && whole.contains(&(x2, y2)));
+                let cropped: Vec<(i32, i32)> = whole
+                    .into_iter()
+                    .filter(|&(x, y)| SIZE.contains(x, y))
+                    .collect();
+                assert_eq!(
+                    Image::line_pixels(x1, y1, x2, y2, SIZE),
+                    cropped,
+                    "({x1}, {y1}) to ({x2}, {y2})"
+                );
+            }
+        }
+    }
+
+    /// Runs typed commands on a new 10 * 10 image, then returns the dump of the session
+    fn dump_after(commands: &[&str], canonical: bool, header: bool) -> String {
+        let mut image = Image::new(10, 10, false);
+        let mut cli = Cli::new(String::new(), &mut image);
+        for command in commands {
+            cli.parse_command(command.to_string());
+        }
+        cli.dump_script(false, canonical, header)
+    }
+
+    #[test]
+    fn canonical_dumps_of_equivalent_sessions_are_equal() {
+        let abbreviated = [
+            "dr 1 1 3 2 true",
+            "mode xor",
+            "w 0 0 T",
+            "DL 0 0 9 9 t XOR",
+            "m SET",
+        ];
+        let full = [
+            "draw_rectangle 1 1 3 2 t",
+            "mode toggle",
+            "write 0 0 t",
+            "draw_line 0 0 9 9 t toggle",
+            "mode set",
+        ];
+        let expected = "canvas 10 10 f;
+draw_rectangle 1 1 3 2 t;
+mode toggle;
+write 0 0 t;
+draw_line 0 0 9 9 t toggle;
+mode set;
+";
+        assert_eq!(dump_after(&abbreviated, true, false), expected);
+        assert_eq!(dump_after(&full, true, false), expected);
+        // The plain dump keeps the commands as they were typed
+        assert_eq!(
+            dump_after(&abbreviated, false, false),
+            "canvas 10 10 f;\ndr 1 1 3 2 true;\nmode xor;\nw 0 0 T;\nDL 0 0 9 9 t XOR;\nm SET;\n"
+        );
+    }
+
+    #[test]
+    fn canonical_form_keeps_paths() {
+        for (raw, canonical) in [
+            ("P load Pat.pbm", "pattern load Pat.pbm"),
+            (
+                "import Photos/Cat.PGM threshold 128",
+                "import Photos/Cat.PGM threshold 128",
+            ),
+            ("dilate load Disk.pbm 2", "dilate load Disk.pbm 2"),
+            ("Export SVG out/T.svg traced", "export SVG out/T.svg traced"),
+            ("w 1 1 TRUE xor", "write 1 1 t toggle"),
+        ] {
+            assert_eq!(LogEntry::new(raw).canonical, canonical, "{raw}");
+        }
+    }
+
+    #[test]
+    fn dump_header_gives_the_image_size() {
+        assert_eq!(
+            dump_after(&["dr 1 1 3 2 t"], false, true),
+            "# ipcli script, 10x10 image\ncanvas 10 10 f;\ndr 1 1 3 2 t;\n"
+        );
+        // A first `canvas` is the initial image, and the header gives the final size
+        assert_eq!(
+            dump_after(&["canvas 6 4 t", "resize 8 5"], true, true),
+            "# ipcli script, 8x5 image\ncanvas 6 4 t;\nresize 8 5;\n"
+        );
+    }
+
+    #[test]
+    fn huge_shapes_only_visit_the_image() {
+        let mut image = Image::new(10, 10, false);
+        image.draw_circle_outline(5, 5, 200_000_000, true);
+        image.draw_circle(-1_000_000_000, 5, 1_000_000_003, true);
+        image.draw_line(-1_000_000_000, 0, 9, 9, true);
+        image.draw_line(i32::MIN, i32::MIN, i32::MAX, i32::MAX, true);
+        image.draw_curve(-2_000_000_000, 0, 5, 5, 2_000_000_000, 0, true);
+        image.draw_rectangle_outline(-5, -5, i32::MAX, i32::MAX, true);
+        // Both lines end on the last pixel
+        assert!(image.grid[9][9]);
+    }
+
+    #[test]
+    fn image_sizes_are_bounded() {
+        let mut image = Image::new(10, 10, false);
+        let mut cli = Cli::new(String::new(), &mut image);
+        for command in [
+            "canvas 100000 100000 t",
+            "canvas 0 5 t",
+            "resize 5 10001",
+            "resize 5 -1",
+        ] {
+            cli.error = None;
+            cli.parse_command(command.to_string());
+            assert_eq!(
+                cli.error.as_deref(),
+                Some("width and height must be between 1 and 10000"),
+                "{command}"
+            );
+            assert_eq!((cli.image.get_width(), cli.image.get_height()), (10, 10));
+        }
+        // Failed commands aren't logged
+        assert_eq!(cli.dump_script(false, false, false), "canvas 10 10 f;\n");
+        cli.parse_command("canvas 10000 1 t".to_string());
+        cli.parse_command("resize 3 10000".to_string());
+        assert_eq!((cli.image.get_width(), cli.image.get_height()), (3, 10000));
     }
 }
@@ -0,0 +1,190 @@
+//! Morphological operations on the image
+
+use crate::pnm;
+use crate::Image;
+
+/// Set of offsets from the origin of a structuring element
+#[derive(Clone, PartialEq, Debug)]
+pub struct StructuringElement {
+    offsets: Vec<(i32, i32)>,
+}
+
+impl StructuringElement {
+    fn from_function(radius: i32, function: impl Fn(i32, i32) -> bool) -> StructuringElement {
+        let mut offsets: Vec<(i32, i32)> = Vec::new();
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                if function(dx, dy) {
+                    offsets.push((dx, dy));
+                }
+            }
+        }
+        StructuringElement { offsets }
+    }
+    pub fn square(radius: i32) -> StructuringElement {
+        StructuringElement::from_function(radius, |_, _| true)
+    }
+    pub fn cross(radius: i32) -> StructuringElement {
+        StructuringElement::from_function(radius, |dx, dy| dx == 0 || dy == 0)
+    }
+    pub fn disk(radius: i32) -> StructuringElement {
+        StructuringElement::from_function(radius, |dx, dy| is_in_disk(dx, dy, radius))
+    }
+    /// Builds the `square`, `cross` or `disk` element of `radius`, which can't exceed `max_radius`
+    pub fn shape(name: &str, radius: i32, max_radius: i32) -> Result<StructuringElement, String> {
+        if radius > max_radius {
+            return Err(format!(
+                "radius can't be larger than the image ({max_radius})"
+            ));
+        }
+        Ok(match name {
+            "square" => StructuringElement::square(radius),
+            "cross" => StructuringElement::cross(radius),
+            _ => StructuringElement::disk(radius),
+        })
+    }
+    /// Loads a structuring element from a PBM bitmap, whose origin is its centre
+    pub fn load(path: &str) -> Result<StructuringElement, String> {
+        let bitmap = pnm::read_pbm(path)?;
+        let origin_x = bitmap[0].len() as i32 / 2;
+        let origin_y = bitmap.len() as i32 / 2;
+        let mut offsets: Vec<(i32, i32)> = Vec::new();
+        for (y, row) in bitmap.iter().enumerate() {
+            for (x, &pixel) in row.iter().enumerate() {
+                if pixel {
+                    offsets.push((x as i32 - origin_x, y as i32 - origin_y));
+                }
+            }
+        }
+        if offsets.is_empty() {
+            return Err(format!("'{path}' has no set pixels"));
+        }
+        Ok(StructuringElement { offsets })
+    }
+}
+
+/// Whether the offset is within `radius` of the origin, computed without overflowing
+fn is_in_disk(dx: i32, dy: i32, radius: i32) -> bool {
+    let square = |value: i32| (value as i64) * (value as i64);
+    square(dx) + square(dy) <= square(radius)
+}
+
+impl Image {
+    /// Sets each selected pixel to whether any (or all) of its neighbours under `element` are set
+    fn apply_neighbourhood(&mut self, element: &StructuringElement, any: bool) {
+        let source = self.grid.clone();
+        let width = self.get_width() as i32;
+        let height = self.get_height() as i32;
+        let read = |x: i32, y: i32| {
+            x >= 0 && y >= 0 && x < width && y < height && source[y as usize][x as usize]
+        };
+        for y in 0..height {
+            for x in 0..width {
+                if !self.is_selected(x, y) {
+                    continue;
+                }
+                let mut neighbourhood =
+                    element.offsets.iter().map(|&(dx, dy)| read(x + dx, y + dy));
+                self.grid[y as usize][x as usize] = if any {
+                    neighbourhood.any(|pixel| pixel)
+                } else {
+                    neighbourhood.all(|pixel| pixel)
+                };
+            }
+        }
+    }
+    /// Grows the shapes drawn in `true`
+    pub fn dilate(&mut self, element: &StructuringElement, iterations: u32) {
+        // Dilation uses the reflection of the element
+        let reflected = StructuringElement {
+            offsets: element.offsets.iter().map(|&(dx, dy)| (-dx, -dy)).collect(),
+        };
+        for _ in 0..iterations {
+            self.apply_neighbourhood(&reflected, true);
+        }
+    }
+    /// Shrinks the shapes drawn in `true`; pixels outside the image count as `false`
+    pub fn erode(&mut self, element: &StructuringElement, iterations: u32) {
+        for _ in 0..iterations {
+            self.apply_neighbourhood(element, false);
+        }
+    }
+    /// Erodes then dilates, removing specks smaller than the element
+    pub fn open(&mut self, element: &StructuringElement, iterations: u32) {
+        self.erode(element, iterations);
+        self.dilate(element, iterations);
+    }
+    /// Dilates then erodes, filling holes smaller than the element
+    pub fn close(&mut self, element: &StructuringElement, iterations: u32) {
+        self.dilate(element, iterations);
+        self.erode(element, iterations);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_in_disk, StructuringElement};
+
+    /// Draws the element as rows of `#` and `.` around its origin
+    fn draw(element: &StructuringElement, radius: i32) -> Vec<String> {
+        (-radius..=radius)
+            .map(|dy| {
+                (-radius..=radius)
+                    .map(|dx| {
+                        if element.offsets.contains(&(dx, dy)) {
+                            '#'
+                        } else {
+                            '.'
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn shapes() {
+        assert_eq!(
+            draw(&StructuringElement::square(1), 1),
+            ["###", "###", "###"]
+        );
+        assert_eq!(
+            draw(&StructuringElement::cross(2), 2),
+            ["..#..", "..#..", "#####", "..#..", "..#.."]
+        );
+        assert_eq!(
+            draw(&StructuringElement::disk(2), 2),
+            ["..#..", ".###.", "#####", ".###.", "..#.."]
+        );
+        assert_eq!(
+            draw(&StructuringElement::disk(3), 3),
+            ["...#...", ".#####.", ".#####.", "#######", ".#####.", ".#####.", "...#..."]
+        );
+        assert_eq!(draw(&StructuringElement::disk(0), 0), ["#"]);
+    }
+
+    #[test]
+    fn large_disks_dont_overflow() {
+        // The squared radius is larger than `i32::MAX`
+        assert!(is_in_disk(50_000, 0, 50_000));
+        assert!(is_in_disk(-30_000, 40_000, 50_000));
+        assert!(!is_in_disk(50_000, 1, 50_000));
+        assert!(!is_in_disk(i32::MIN, 0, i32::MAX));
+    }
+
+    #[test]
+    fn radius_larger_than_the_image() {
+        assert_eq!(
+            StructuringElement::shape("disk", 50_000, 20),
+            Err("radius can't be larger than the image (20)".to_string())
+        );
+        assert_eq!(
+            StructuringElement::shape("square", 30_000, 20),
+            Err("radius can't be larger than the image (20)".to_string())
+        );
+        assert_eq!(
+            StructuringElement::shape("cross", 20, 20),
+            Ok(StructuringElement::cross(20))
+        );
+    }
+}
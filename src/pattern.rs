@@ -0,0 +1,53 @@
+//! Fill patterns used by filled shapes and flood fills
+
+use crate::pnm;
+
+/// 4×4 ordered dithering matrix, tiled to make the shades
+const BAYER_4X4: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Pattern {
+    name: String,
+    cells: Vec<Vec<bool>>,
+}
+
+impl Pattern {
+    fn from_function(name: String, function: impl Fn(usize, usize) -> bool) -> Pattern {
+        let cells = (0..8)
+            .map(|y| (0..8).map(|x| function(x, y)).collect())
+            .collect();
+        Pattern { name, cells }
+    }
+    pub fn checkerboard() -> Pattern {
+        Pattern::from_function("checkerboard".to_owned(), |x, y| (x + y) % 2 == 0)
+    }
+    pub fn hatch() -> Pattern {
+        Pattern::from_function("hatch".to_owned(), |x, y| (x + y) % 4 == 0)
+    }
+    /// Returns the Bayer shade `level`, from 0 (empty) to 16 (solid)
+    pub fn shade(level: u8) -> Option<Pattern> {
+        if level > 16 {
+            return None;
+        }
+        Some(Pattern::from_function(format!("shade {level}"), |x, y| {
+            BAYER_4X4[y % 4][x % 4] < level
+        }))
+    }
+    /// Loads a pattern from a PBM bitmap
+    pub fn load(path: &str) -> Result<Pattern, String> {
+        let cells = pnm::read_pbm(path)?;
+        Ok(Pattern {
+            name: format!("load {path}"),
+            cells,
+        })
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    /// Whether the pattern is set at (x, y); the pattern is anchored to the image origin
+    pub fn at(&self, x: i32, y: i32) -> bool {
+        let height = self.cells.len() as i32;
+        let width = self.cells[0].len() as i32;
+        self.cells[y.rem_euclid(height) as usize][x.rem_euclid(width) as usize]
+    }
+}
@@ -0,0 +1,210 @@
+//! Readers and writer for the Netpbm image formats
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG";
+
+/// Reads a PBM (P1 or P4) file, `true` being a black pixel
+pub fn read_pbm(path: &str) -> Result<Vec<Vec<bool>>, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("failed to read '{path}': {e}"))?;
+    let mut reader = Reader {
+        bytes: &bytes,
+        position: 0,
+    };
+    let magic = reader.token()?;
+    let width = reader.number()?;
+    let height = reader.number()?;
+    if width == 0 || height == 0 {
+        return Err(format!("'{path}' is empty"));
+    }
+    // Plain pixels take at least a character, packed rows a byte per 8 pixels
+    let pixel_count = match magic.as_str() {
+        "P4" => width.div_ceil(8).checked_mul(height),
+        _ => width.checked_mul(height),
+    };
+    if !reader.has_remaining(pixel_count, 1) {
+        return Err(format!("'{path}' is smaller than its dimensions"));
+    }
+    let mut grid: Vec<Vec<bool>> = vec![vec![false; width]; height];
+    match magic.as_str() {
+        "P1" => {
+            for row in grid.iter_mut() {
+                for pixel in row.iter_mut() {
+                    *pixel = reader.bit()?;
+                }
+            }
+        }
+        "P4" => {
+            // A single whitespace character separates the header from the raster
+            reader.position += 1;
+            let row_length = width.div_ceil(8);
+            for row in grid.iter_mut() {
+                let row_bytes = reader.raw(row_length)?;
+                for (x, pixel) in row.iter_mut().enumerate() {
+                    *pixel = row_bytes[x / 8] & (0x80 >> (x % 8)) != 0;
+                }
+            }
+        }
+        _ => return Err(format!("'{path}' is not a PBM file")),
+    }
+    Ok(grid)
+}
+
+/// Writes a plain PBM (P1) file, one row per line unless it's longer than 70 pixels
+pub fn write_pbm(path: &str, grid: &[Vec<bool>]) -> Result<(), String> {
+    let width = grid.first().map_or(0, Vec::len);
+    let mut text = format!("P1\n{width} {}\n", grid.len());
+    for row in grid {
+        for chunk in row.chunks(70) {
+            text.extend(chunk.iter().map(|&pixel| if pixel { '1' } else { '0' }));
+            text.push('\n');
+        }
+    }
+    std::fs::write(path, text).map_err(|e| format!("failed to write '{path}': {e}"))
+}
+
+/// Reads a PBM, PGM or PPM file as luminance values from 0 (black) to 1 (white)
+pub fn read_luminance(path: &str) -> Result<Vec<Vec<f32>>, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("failed to read '{path}': {e}"))?;
+    if bytes.starts_with(PNG_SIGNATURE) {
+        return Err(format!(
+            "'{path}' is a PNG image, which isn't supported; convert it to PGM or PPM first"
+        ));
+    }
+    let mut reader = Reader {
+        bytes: &bytes,
+        position: 0,
+    };
+    let magic = reader.token()?;
+    if magic == "P1" || magic == "P4" {
+        // Bitmaps have no maximum value
+        let bitmap = read_pbm(path)?;
+        return Ok(bitmap
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|&black| if black { 0.0 } else { 1.0 })
+                    .collect()
+            })
+            .collect());
+    }
+    let width = reader.number()?;
+    let height = reader.number()?;
+    let max_value = reader.number()?;
+    if width == 0 || height == 0 {
+        return Err(format!("'{path}' is empty"));
+    }
+    if max_value == 0 || max_value > 65535 {
+        return Err(format!("'{path}' has an invalid maximum value"));
+    }
+    let channels = match magic.as_str() {
+        "P2" | "P5" => 1,
+        "P3" | "P6" => 3,
+        _ => return Err(format!("'{path}' is not a PBM, PGM or PPM file")),
+    };
+    if magic == "P5" || magic == "P6" {
+        reader.position += 1;
+    }
+    // Plain samples take at least a character, raw samples one or two bytes
+    let sample_size = if (magic == "P5" || magic == "P6") && max_value >= 256 {
+        2
+    } else {
+        1
+    };
+    let sample_count = width
+        .checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(channels));
+    if !reader.has_remaining(sample_count, sample_size) {
+        return Err(format!("'{path}' is smaller than its dimensions"));
+    }
+    let mut luminance: Vec<Vec<f32>> = vec![vec![0.0; width]; height];
+    for row in luminance.iter_mut() {
+        for pixel in row.iter_mut() {
+            let mut samples = [0.0; 3];
+            for sample in samples.iter_mut().take(channels) {
+                let value = match magic.as_str() {
+                    "P2" | "P3" => reader.number()?,
+                    _ if max_value < 256 => reader.raw(1)?[0] as usize,
+                    _ => {
+                        let pair = reader.raw(2)?;
+                        (pair[0] as usize) << 8 | pair[1] as usize
+                    }
+                };
+                *sample = value.min(max_value) as f32 / max_value as f32;
+            }
+            *pixel = if channels == 1 {
+                samples[0]
+            } else {
+                0.299 * samples[0] + 0.587 * samples[1] + 0.114 * samples[2]
+            };
+        }
+    }
+    Ok(luminance)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn skip_whitespace_and_comments(&mut self) {
+        while self.position < self.bytes.len() {
+            match self.bytes[self.position] {
+                b'#' => {
+                    while self.position < self.bytes.len() && self.bytes[self.position] != b'\n' {
+                        self.position += 1;
+                    }
+                }
+                byte if byte.is_ascii_whitespace() => self.position += 1,
+                _ => break,
+            }
+        }
+    }
+    fn token(&mut self) -> Result<String, String> {
+        self.skip_whitespace_and_comments();
+        let start = self.position;
+        while self.position < self.bytes.len() && !self.bytes[self.position].is_ascii_whitespace() {
+            self.position += 1;
+        }
+        if start == self.position {
+            return Err("unexpected end of file".to_owned());
+        }
+        Ok(String::from_utf8_lossy(&self.bytes[start..self.position]).into_owned())
+    }
+    fn number(&mut self) -> Result<usize, String> {
+        let token = self.token()?;
+        token
+            .parse()
+            .map_err(|_| format!("invalid number '{token}'"))
+    }
+    /// Reads a single `0` or `1`, which may not be separated by whitespace
+    fn bit(&mut self) -> Result<bool, String> {
+        self.skip_whitespace_and_comments();
+        match self.bytes.get(self.position) {
+            Some(b'0') => {
+                self.position += 1;
+                Ok(false)
+            }
+            Some(b'1') => {
+                self.position += 1;
+                Ok(true)
+            }
+            Some(_) => Err("invalid pixel value".to_owned()),
+            None => Err("unexpected end of file".to_owned()),
+        }
+    }
+    /// Returns whether `count` items of `size` bytes fit in the rest of the file, so that they
+    /// can be allocated
+    fn has_remaining(&self, count: Option<usize>, size: usize) -> bool {
+        count
+            .and_then(|count| count.checked_mul(size))
+            .is_some_and(|length| length <= self.bytes.len().saturating_sub(self.position))
+    }
+    fn raw(&mut self, length: usize) -> Result<&[u8], String> {
+        if self.position + length > self.bytes.len() {
+            return Err("unexpected end of file".to_owned());
+        }
+        let slice = &self.bytes[self.position..self.position + length];
+        self.position += length;
+        Ok(slice)
+    }
+}
@@ -0,0 +1,194 @@
+//! Reconstruction of a script drawing the current image
+
+use crate::{Cli, DrawMode, Image, Rect};
+
+impl Image {
+    /// Returns commands drawing this image over any image of the same size
+    ///
+    /// The image is cleared with its dominant color, then the other pixels are covered with
+    /// rectangles, single pixels being written.
+    pub fn to_script(&self) -> Vec<String> {
+        let width = self.get_width();
+        let height = self.get_height();
+        let set_pixels = self.grid.iter().flatten().filter(|&&pixel| pixel).count();
+        let dominant = set_pixels * 2 > width * height;
+        let color = if dominant { "f" } else { "t" };
+        let mut script = vec![
+            "mode set".to_owned(),
+            "pattern solid".to_owned(),
+            "clip none".to_owned(),
+            format!("clear {}", if dominant { "t" } else { "f" }),
+        ];
+        let mut covered = vec![vec![false; width]; height];
+        for y in 0..height {
+            for x in 0..width {
+                if covered[y][x] || self.grid[y][x] == dominant {
+                    continue;
+                }
+                let (w, h) = self.largest_rectangle(x, y, dominant, &covered);
+                for row in covered.iter_mut().skip(y).take(h) {
+                    for pixel in row.iter_mut().skip(x).take(w) {
+                        *pixel = true;
+                    }
+                }
+                if w == 1 && h == 1 {
+                    script.push(format!("write {x} {y} {color}"));
+                } else {
+                    script.push(format!("draw_rectangle {x} {y} {w} {h} {color}"));
+                }
+            }
+        }
+        script
+    }
+    /// Returns the size of the rectangle at (x, y) without pixels of color `dominant` covering the
+    /// most uncovered pixels, growing either rightwards or downwards first
+    fn largest_rectangle(
+        &self,
+        x: usize,
+        y: usize,
+        dominant: bool,
+        covered: &[Vec<bool>],
+    ) -> (usize, usize) {
+        let width = self.get_width();
+        let height = self.get_height();
+        let fits = |x0: usize, y0: usize, w: usize, h: usize| {
+            (y0..y0 + h).all(|y| (x0..x0 + w).all(|x| self.grid[y][x] != dominant))
+        };
+        let uncovered = |w: usize, h: usize| {
+            (y..y + h)
+                .map(|y| (x..x + w).filter(|&x| !covered[y][x]).count())
+                .sum::<usize>()
+        };
+        // Rightwards first
+        let mut w1 = 1;
+        while x + w1 < width && fits(x + w1, y, 1, 1) {
+            w1 += 1;
+        }
+        let mut h1 = 1;
+        while y + h1 < height && fits(x, y + h1, w1, 1) {
+            h1 += 1;
+        }
+        // Downwards first
+        let mut h2 = 1;
+        while y + h2 < height && fits(x, y + h2, 1, 1) {
+            h2 += 1;
+        }
+        let mut w2 = 1;
+        while x + w2 < width && fits(x + w2, y, 1, h2) {
+            w2 += 1;
+        }
+        if uncovered(w1, h1) >= uncovered(w2, h2) {
+            (w1, h1)
+        } else {
+            (w2, h2)
+        }
+    }
+}
+
+impl Cli<'_> {
+    /// Whether running `script` draws the current image over an image of the same size, whatever
+    /// its pixels, drawing mode and clipping region
+    pub fn verify_script(&self, script: &[String]) -> bool {
+        let width = self.image.get_width() as i32;
+        let height = self.image.get_height() as i32;
+        [false, true].into_iter().all(|color| {
+            let mut image = Image::new(width, height, color);
+            image.draw_mode = DrawMode::Toggle;
+            image.clip = Some(Rect {
+                x: 0,
+                y: 0,
+                w: 1,
+                h: 1,
+            });
+            let mut cli = Cli::new(String::new(), &mut image);
+            for command in script {
+                cli.parse_command(command.to_owned());
+            }
+            image.grid == self.image.grid
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Cli, Image};
+
+    /// Returns an image whose pixel at (x, y) is `pixel(x, y)`
+    fn image(width: i32, height: i32, pixel: impl Fn(usize, usize) -> bool) -> Image {
+        let mut image = Image::new(width, height, false);
+        for (y, line) in image.grid.iter_mut().enumerate() {
+            for (x, value) in line.iter_mut().enumerate() {
+                *value = pixel(x, y);
+            }
+        }
+        image
+    }
+
+    fn assert_round_trip(mut image: Image, name: &str) {
+        let script = image.to_script();
+        let (width, height) = (image.get_width(), image.get_height());
+        let cli = Cli::new(String::new(), &mut image);
+        assert!(cli.verify_script(&script), "{name}: {script:?}");
+        // Rectangles cover runs of pixels
+        assert!(
+            script.len() <= 4 + width * height / 2 + 1,
+            "{name}: {script:?}"
+        );
+    }
+
+    #[test]
+    fn patterned_images_round_trip() {
+        assert_round_trip(image(1, 1, |_, _| true), "single pixel");
+        assert_round_trip(image(7, 5, |_, _| false), "empty");
+        assert_round_trip(image(7, 5, |_, _| true), "full");
+        assert_round_trip(image(9, 6, |x, y| (x + y) % 2 == 0), "checkerboard");
+        assert_round_trip(image(9, 6, |x, _| x % 3 == 0), "stripes");
+        assert_round_trip(
+            image(12, 10, |x, y| {
+                (2..9).contains(&x) && (3..7).contains(&y) && x != 5
+            }),
+            "rectangles",
+        );
+        assert_round_trip(
+            image(12, 10, |x, y| !(x == 4 && y == 4)),
+            "hole in a full image",
+        );
+    }
+
+    #[test]
+    fn random_images_round_trip() {
+        // Linear congruential generator, for reproducible images
+        let mut state: u64 = 0x2545f4914f6cdd1d;
+        for size in 1..=16 {
+            for density in [1, 4, 8, 12, 15] {
+                let mut bits: Vec<bool> = Vec::new();
+                for _ in 0..size * size {
+                    state = state
+                        .wrapping_mul(6364136223846793005)
+                        .wrapping_add(1442695040888963407);
+                    bits.push((state >> 60) < density);
+                }
+                let width = size as usize;
+                assert_round_trip(
+                    image(size, size, |x, y| bits[y * width + x]),
+                    &format!("{size} * {size}, density {density}/16"),
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn dominant_color_is_cleared() {
+        let script = image(4, 3, |x, _| x != 0).to_script();
+        assert_eq!(
+            script,
+            [
+                "mode set",
+                "pattern solid",
+                "clip none",
+                "clear t",
+                "draw_rectangle 0 0 1 3 f"
+            ]
+        );
+    }
+}
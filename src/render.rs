@@ -0,0 +1,409 @@
+//! Rendering of the image as text for the terminal
+
+use crate::theme::Theme;
+use crate::Image;
+use std::ops::Range;
+
+/// Distances between ruler ticks, in pixels, of which the smallest fitting is used
+const TICK_SPACINGS: [usize; 13] = [1, 2, 5, 10, 20, 50, 100, 200, 500, 1000, 2000, 5000, 10000];
+
+/// Quadrant block characters, indexed by the top-left (1), top-right (2), bottom-left (4) and
+/// bottom-right (8) pixels
+const QUADRANTS: [char; 16] = [
+    ' ', '▘', '▝', '▀', '▖', '▌', '▞', '▛', '▗', '▚', '▐', '▜', '▄', '▙', '▟', '█',
+];
+
+/// Bits of the Braille dots, indexed by row then column
+const BRAILLE_DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+/// How pixels are packed into characters
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RenderMode {
+    /// Two full blocks per pixel
+    Blocks,
+    /// Half blocks, 1 * 2 pixels per character
+    Half,
+    /// Quadrant blocks, 2 * 2 pixels per character
+    Quadrant,
+    /// Braille patterns, 2 * 4 pixels per character
+    Braille,
+}
+
+impl RenderMode {
+    pub fn from_name(name: &str) -> Option<RenderMode> {
+        match name {
+            "blocks" => Some(RenderMode::Blocks),
+            "half" => Some(RenderMode::Half),
+            "quadrant" => Some(RenderMode::Quadrant),
+            "braille" => Some(RenderMode::Braille),
+            _ => None,
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            RenderMode::Blocks => "blocks",
+            RenderMode::Half => "half",
+            RenderMode::Quadrant => "quadrant",
+            RenderMode::Braille => "braille",
+        }
+    }
+    /// Number of pixels covered by a character, horizontally and vertically
+    pub fn cell_size(&self) -> (usize, usize) {
+        match self {
+            RenderMode::Blocks => (1, 1),
+            RenderMode::Half => (1, 2),
+            RenderMode::Quadrant => (2, 2),
+            RenderMode::Braille => (2, 4),
+        }
+    }
+    /// Number of terminal columns taken by a cell
+    pub fn cell_width(&self) -> usize {
+        match self {
+            RenderMode::Blocks => 2,
+            _ => 1,
+        }
+    }
+}
+
+/// Settings of the text rendering of the image
+#[derive(Clone, PartialEq, Debug)]
+pub struct RenderOptions {
+    pub mode: RenderMode,
+    pub frame: bool,
+    /// Whether coordinates are labelled along the top and left of the frame
+    pub rulers: bool,
+    /// Distance between the lines of the dotted grid, in pixels
+    pub grid: Option<usize>,
+    /// Pixel marked by a horizontal and a vertical line
+    pub crosshair: Option<(usize, usize)>,
+    pub theme: Theme,
+}
+
+impl Default for RenderOptions {
+    fn default() -> RenderOptions {
+        RenderOptions {
+            mode: RenderMode::Blocks,
+            frame: true,
+            rulers: false,
+            grid: None,
+            crosshair: None,
+            theme: Theme::default(),
+        }
+    }
+}
+
+impl RenderOptions {
+    /// Returns the character drawn over an empty cell showing `columns` * `rows` pixels, and the
+    /// one filling the rest of wider cells
+    pub fn overlay(&self, columns: &Range<usize>, rows: &Range<usize>) -> Option<(char, char)> {
+        if let Some((x, y)) = self.crosshair {
+            match (columns.contains(&x), rows.contains(&y)) {
+                (true, true) => return Some(('┼', '─')),
+                (true, false) => return Some(('│', ' ')),
+                (false, true) => return Some(('─', '─')),
+                (false, false) => {}
+            }
+        }
+        let spacing = self.grid?;
+        match (
+            first_multiple(columns, spacing).is_some(),
+            first_multiple(rows, spacing).is_some(),
+        ) {
+            (_, true) => Some(('·', '·')),
+            (true, false) => Some(('·', ' ')),
+            (false, false) => None,
+        }
+    }
+}
+
+/// Returns the first multiple of `n` in `range`
+fn first_multiple(range: &Range<usize>, n: usize) -> Option<usize> {
+    let multiple = range.start.div_ceil(n) * n;
+    (multiple < range.end).then_some(multiple)
+}
+
+/// Returns the coordinate labelled on each cell of a ruler, if any
+///
+/// `ranges` are the pixels shown by each cell, and ticks are at least `min_distance` characters
+/// apart.
+pub fn ruler_ticks(
+    ranges: &[Range<usize>],
+    cell_width: usize,
+    min_distance: usize,
+) -> Vec<Option<usize>> {
+    let (Some(first), Some(last)) = (ranges.first(), ranges.last()) else {
+        return Vec::new();
+    };
+    let characters_per_pixel = (ranges.len() * cell_width) as f32 / (last.end - first.start) as f32;
+    let spacing = TICK_SPACINGS
+        .into_iter()
+        .find(|&spacing| spacing as f32 * characters_per_pixel >= min_distance as f32)
+        .unwrap_or(TICK_SPACINGS[TICK_SPACINGS.len() - 1]);
+    let mut previous_tick = None;
+    ranges
+        .iter()
+        .map(|range| {
+            // A pixel shown by several cells is labelled on the first one
+            let tick = first_multiple(range, spacing).filter(|&tick| previous_tick != Some(tick));
+            previous_tick = tick.or(previous_tick);
+            tick
+        })
+        .collect()
+}
+
+/// Returns the line of labels of a horizontal ruler, starting after `indent` characters
+pub fn ruler_labels(ticks: &[Option<usize>], cell_width: usize, indent: usize) -> String {
+    let mut line: Vec<char> = vec![' '; indent + ticks.len() * cell_width];
+    let mut next_free = 0;
+    for (i, tick) in ticks.iter().enumerate() {
+        let position = indent + i * cell_width;
+        let Some(tick) = tick else {
+            continue;
+        };
+        let label: Vec<char> = tick.to_string().chars().collect();
+        if position < next_free || position + label.len() > line.len() {
+            continue;
+        }
+        line[position..position + label.len()].copy_from_slice(&label);
+        next_free = position + label.len() + 1;
+    }
+    line.into_iter().collect::<String>().trim_end().to_owned()
+}
+
+impl Image {
+    /// Returns the characters of the cell at (column, row), pixels past the edges being `false`
+    ///
+    /// The `blocks` mode draws the glyphs of `theme`.
+    pub fn render_cell(
+        &self,
+        mode: RenderMode,
+        theme: &Theme,
+        column: usize,
+        row: usize,
+    ) -> String {
+        let (cell_width, cell_height) = mode.cell_size();
+        let pixel = |dx: usize, dy: usize| {
+            self.grid
+                .get(row * cell_height + dy)
+                .and_then(|line| line.get(column * cell_width + dx))
+                .copied()
+                .unwrap_or(false)
+        };
+        match mode {
+            RenderMode::Blocks => {
+                let glyph = if pixel(0, 0) { theme.on } else { theme.off };
+                [glyph, glyph].iter().collect()
+            }
+            RenderMode::Half => match (pixel(0, 0), pixel(0, 1)) {
+                (false, false) => " ",
+                (true, false) => "▀",
+                (false, true) => "▄",
+                (true, true) => "█",
+            }
+            .to_owned(),
+            RenderMode::Quadrant => {
+                let index = pixel(0, 0) as usize
+                    | (pixel(1, 0) as usize) << 1
+                    | (pixel(0, 1) as usize) << 2
+                    | (pixel(1, 1) as usize) << 3;
+                QUADRANTS[index].to_string()
+            }
+            RenderMode::Braille => {
+                let mut dots = 0;
+                for (dy, row_dots) in BRAILLE_DOTS.iter().enumerate() {
+                    for (dx, dot) in row_dots.iter().enumerate() {
+                        if pixel(dx, dy) {
+                            dots |= dot;
+                        }
+                    }
+                }
+                if dots == 0 {
+                    // Blank Braille patterns can be narrower than a space in some fonts
+                    " ".to_owned()
+                } else {
+                    char::from_u32(0x2800 + dots).unwrap().to_string()
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ruler_ticks, RenderMode};
+    use crate::theme::Theme;
+    use crate::viewport::Viewport;
+    use crate::Image;
+
+    /// Returns the characters of every cell of the image, row by row
+    fn cells(rows: &[&str], mode: RenderMode, theme: &Theme) -> Vec<String> {
+        let image = Image::from_rows(rows);
+        let (cell_width, cell_height) = mode.cell_size();
+        (0..image.get_height().div_ceil(cell_height))
+            .map(|row| {
+                (0..image.get_width().div_ceil(cell_width))
+                    .map(|column| image.render_cell(mode, theme, column, row))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Returns the (cell, coordinate) of the ticks of a ruler of `cells` cells of `mode`, starting
+    /// at pixel `origin`
+    fn ticks(
+        zoom: i32,
+        origin: usize,
+        cells: usize,
+        mode: RenderMode,
+        min_distance: usize,
+    ) -> Vec<(usize, usize)> {
+        let viewport = Viewport {
+            x: origin,
+            y: 0,
+            zoom,
+        };
+        let (cell_width, _) = mode.cell_size();
+        let ranges: Vec<std::ops::Range<usize>> = (0..cells)
+            .map(|cell| viewport.pixel_range(origin, cell * cell_width, cell_width))
+            .collect();
+        ruler_ticks(&ranges, mode.cell_width(), min_distance)
+            .into_iter()
+            .enumerate()
+            .filter_map(|(cell, tick)| Some((cell, tick?)))
+            .collect()
+    }
+
+    #[test]
+    fn ruler_ticks_are_spaced_for_the_zoom() {
+        // Pixels are 2 characters wide, so ticks every 2 pixels are 4 characters apart
+        assert_eq!(
+            ticks(0, 0, 9, RenderMode::Blocks, 3),
+            [(0, 0), (2, 2), (4, 4), (6, 6), (8, 8)]
+        );
+        assert_eq!(
+            ticks(0, 0, 12, RenderMode::Blocks, 5),
+            [(0, 0), (5, 5), (10, 10)]
+        );
+        // Zoomed in, every pixel is labelled once, on the first of its cells
+        assert_eq!(
+            ticks(2, 0, 10, RenderMode::Blocks, 3),
+            [(0, 0), (4, 1), (8, 2)]
+        );
+        // Zoomed out, a cell shows 8 pixels, so ticks are 50 pixels apart
+        assert_eq!(
+            ticks(-2, 0, 16, RenderMode::Braille, 3),
+            [(0, 0), (6, 50), (12, 100)]
+        );
+        // A quadrant cell shows 32 pixels at 1:16
+        assert_eq!(
+            ticks(-4, 0, 20, RenderMode::Quadrant, 3),
+            [
+                (0, 0),
+                (3, 100),
+                (6, 200),
+                (9, 300),
+                (12, 400),
+                (15, 500),
+                (18, 600)
+            ]
+        );
+    }
+
+    #[test]
+    fn ruler_ticks_start_at_the_origin() {
+        assert_eq!(
+            ticks(0, 3, 10, RenderMode::Blocks, 3),
+            [(1, 4), (3, 6), (5, 8), (7, 10), (9, 12)]
+        );
+        assert_eq!(
+            ticks(-2, 13, 16, RenderMode::Braille, 3),
+            [(4, 50), (10, 100)]
+        );
+        assert!(ticks(0, 0, 0, RenderMode::Blocks, 3).is_empty());
+    }
+
+    #[test]
+    fn blocks() {
+        let theme = Theme {
+            on: '#',
+            off: '.',
+            ..Theme::default()
+        };
+        assert_eq!(
+            cells(&["#.", ".#"], RenderMode::Blocks, &theme),
+            ["##..", "..##"]
+        );
+        assert_eq!(
+            cells(&["#."], RenderMode::Blocks, &Theme::default()),
+            ["██  "]
+        );
+    }
+
+    #[test]
+    fn half_blocks() {
+        assert_eq!(
+            cells(&["..##", ".#.#"], RenderMode::Half, &Theme::default()),
+            [" ▄▀█"]
+        );
+        // The missing bottom half of the last row is `f`
+        assert_eq!(
+            cells(&["#.", ".#", "##"], RenderMode::Half, &Theme::default()),
+            ["▀▄", "▀▀"]
+        );
+    }
+
+    #[test]
+    fn quadrants() {
+        // Every combination, the top-left pixel being the lowest bit
+        let expected = " ▘▝▀▖▌▞▛▗▚▐▜▄▙▟█";
+        for (index, character) in expected.chars().enumerate() {
+            let pixel = |bit: usize| if index >> bit & 1 == 1 { '#' } else { '.' };
+            let top: String = [pixel(0), pixel(1)].iter().collect();
+            let bottom: String = [pixel(2), pixel(3)].iter().collect();
+            assert_eq!(
+                cells(&[&top, &bottom], RenderMode::Quadrant, &Theme::default()),
+                [character.to_string()],
+                "{index}"
+            );
+        }
+        assert_eq!(
+            cells(
+                &["###", "###", "###"],
+                RenderMode::Quadrant,
+                &Theme::default()
+            ),
+            ["█▌", "▀▘"]
+        );
+    }
+
+    #[test]
+    fn braille() {
+        let theme = Theme::default();
+        assert_eq!(
+            cells(&["#.", "..", "..", ".."], RenderMode::Braille, &theme),
+            ["⠁"]
+        );
+        assert_eq!(
+            cells(&["..", "..", "..", ".#"], RenderMode::Braille, &theme),
+            ["⢀"]
+        );
+        assert_eq!(
+            cells(&["#.", "#.", "#.", "#."], RenderMode::Braille, &theme),
+            ["⡇"]
+        );
+        assert_eq!(
+            cells(&[".#", ".#", ".#", ".#"], RenderMode::Braille, &theme),
+            ["⢸"]
+        );
+        assert_eq!(
+            cells(&["##", "##", "##", "##"], RenderMode::Braille, &theme),
+            ["⣿"]
+        );
+        // Blank cells are spaces, and the pixels past the edges are `f`
+        assert_eq!(
+            cells(&["...#", "....", "#..."], RenderMode::Braille, &theme),
+            ["⠄⠈"]
+        );
+        assert_eq!(cells(&["..", ".."], RenderMode::Braille, &theme), [" "]);
+    }
+}
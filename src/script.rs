@@ -0,0 +1,1228 @@
+//! Parser and interpreter of the script language
+
+use crate::{argument_kind, Argument, Cli, COMMANDS, COMMAND_ALIASES};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+/// Names that can't be assigned
+const RESERVED_NAMES: [&str; 8] = ["width", "height", "min", "max", "t", "f", "true", "false"];
+
+/// Words starting statements, which can't name procedures or aliases
+pub const KEYWORDS: [&str; 7] = ["repeat", "for", "if", "else", "def", "include", "param"];
+
+/// Maximum depth of nested procedure calls
+const MAX_CALL_DEPTH: usize = 64;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Expression {
+    Number(i64),
+    Variable(String),
+    Unary(String, Box<Expression>),
+    Binary(String, Box<Expression>, Box<Expression>),
+    Call(String, Vec<Expression>),
+}
+
+/// A statement of a script
+#[derive(Clone, PartialEq, Debug)]
+pub enum Statement {
+    Command(String),
+    Repeat {
+        count: Expression,
+        body: Vec<Statement>,
+    },
+    For {
+        variable: String,
+        start: Expression,
+        end: Expression,
+        step: Expression,
+        body: Vec<Statement>,
+    },
+    If {
+        condition: Expression,
+        body: Vec<Statement>,
+        else_body: Vec<Statement>,
+    },
+    Def {
+        name: String,
+        parameters: Vec<String>,
+        body: Vec<Statement>,
+    },
+    Include(String),
+    Param {
+        name: String,
+        default: Option<Expression>,
+    },
+}
+
+/// A procedure defined with `def`
+#[derive(Clone, PartialEq, Debug)]
+pub struct Procedure {
+    parameters: Vec<String>,
+    body: Vec<Statement>,
+}
+
+/// State of the script interpreter
+#[derive(Default)]
+pub struct Interpreter {
+    /// Variables defined outside of procedures
+    globals: HashMap<String, i64>,
+    /// Local variables of the procedures being called, innermost last
+    frames: Vec<HashMap<String, i64>>,
+    procedures: HashMap<String, Procedure>,
+    /// Scripts being run, innermost last
+    files: Vec<PathBuf>,
+    /// Directories searched for included scripts
+    pub include_paths: Vec<PathBuf>,
+    /// Values of the script's parameters given on the command line
+    pub arguments: HashMap<String, i64>,
+    /// Values given on the command line after `--`, in the order of the parameters
+    pub positional_arguments: Vec<i64>,
+}
+
+impl Interpreter {
+    /// Returns the value of a local variable, or else of a global one
+    pub fn variable(&self, name: &str) -> Option<i64> {
+        self.frames
+            .last()
+            .and_then(|frame| frame.get(name))
+            .or(self.globals.get(name))
+            .copied()
+    }
+    /// Variables assigned by `let` and loops, local within procedures
+    fn scope(&mut self) -> &mut HashMap<String, i64> {
+        match self.frames.last_mut() {
+            Some(frame) => frame,
+            None => &mut self.globals,
+        }
+    }
+    /// Returns the visible variables, sorted by name
+    pub fn visible_variables(&self) -> Vec<(String, i64)> {
+        let mut variables = self.globals.clone();
+        if let Some(frame) = self.frames.last() {
+            variables.extend(frame.clone());
+        }
+        let mut variables: Vec<(String, i64)> = variables.into_iter().collect();
+        variables.sort();
+        variables
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Token {
+    Number(i64),
+    Identifier(String),
+    Symbol(String),
+}
+
+/// Operators, longest first so that `<=` isn't read as `<`
+const SYMBOLS: [&str; 17] = [
+    "==", "!=", "<=", ">=", "&&", "||", "<", ">", "!", "+", "-", "*", "/", "%", "(", ")", ",",
+];
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let characters: Vec<char> = text.chars().collect();
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut i = 0;
+    'characters: while i < characters.len() {
+        let character = characters[i];
+        if character.is_whitespace() {
+            i += 1;
+        } else if character.is_ascii_digit() {
+            let start = i;
+            while i < characters.len() && characters[i].is_ascii_digit() {
+                i += 1;
+            }
+            let digits: String = characters[start..i].iter().collect();
+            let number = digits
+                .parse()
+                .map_err(|_| format!("number '{digits}' is too large"))?;
+            tokens.push(Token::Number(number));
+        } else if character.is_ascii_alphabetic() || character == '_' {
+            let start = i;
+            while i < characters.len()
+                && (characters[i].is_ascii_alphanumeric() || characters[i] == '_')
+            {
+                i += 1;
+            }
+            tokens.push(Token::Identifier(characters[start..i].iter().collect()));
+        } else {
+            for symbol in SYMBOLS {
+                if characters[i..].starts_with(&symbol.chars().collect::<Vec<char>>()) {
+                    tokens.push(Token::Symbol(symbol.to_owned()));
+                    i += symbol.len();
+                    continue 'characters;
+                }
+            }
+            return Err(format!("unexpected character '{character}'"));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent parser, from the lowest precedence to the highest
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn next_symbol_in(&mut self, symbols: &[&str]) -> Option<String> {
+        match self.tokens.get(self.position) {
+            Some(Token::Symbol(symbol)) if symbols.contains(&symbol.as_str()) => {
+                self.position += 1;
+                Some(symbol.to_owned())
+            }
+            _ => None,
+        }
+    }
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), String> {
+        match self.next_symbol_in(&[symbol]) {
+            Some(_) => Ok(()),
+            None => Err(format!("expected '{symbol}'")),
+        }
+    }
+    /// Parses a left-associative sequence of operations of the same precedence
+    fn binary(
+        &mut self,
+        operators: &[&str],
+        operand: fn(&mut Parser) -> Result<Expression, String>,
+    ) -> Result<Expression, String> {
+        let mut expression = operand(self)?;
+        while let Some(operator) = self.next_symbol_in(operators) {
+            expression =
+                Expression::Binary(operator, Box::new(expression), Box::new(operand(self)?));
+        }
+        Ok(expression)
+    }
+    fn or(&mut self) -> Result<Expression, String> {
+        self.binary(&["||"], Parser::and)
+    }
+    fn and(&mut self) -> Result<Expression, String> {
+        self.binary(&["&&"], Parser::comparison)
+    }
+    fn comparison(&mut self) -> Result<Expression, String> {
+        self.binary(&["==", "!=", "<=", ">=", "<", ">"], Parser::sum)
+    }
+    fn sum(&mut self) -> Result<Expression, String> {
+        self.binary(&["+", "-"], Parser::product)
+    }
+    fn product(&mut self) -> Result<Expression, String> {
+        self.binary(&["*", "/", "%"], Parser::unary)
+    }
+    fn unary(&mut self) -> Result<Expression, String> {
+        if let Some(operator) = self.next_symbol_in(&["-", "!"]) {
+            return Ok(Expression::Unary(operator, Box::new(self.unary()?)));
+        }
+        if self.next_symbol_in(&["+"]).is_some() {
+            return self.unary();
+        }
+        self.primary()
+    }
+    fn primary(&mut self) -> Result<Expression, String> {
+        match self.tokens.get(self.position).cloned() {
+            Some(Token::Number(number)) => {
+                self.position += 1;
+                Ok(Expression::Number(number))
+            }
+            Some(Token::Identifier(name)) => {
+                self.position += 1;
+                if self.next_symbol_in(&["("]).is_none() {
+                    return Ok(Expression::Variable(name));
+                }
+                let mut arguments: Vec<Expression> = Vec::new();
+                if self.next_symbol_in(&[")"]).is_none() {
+                    loop {
+                        arguments.push(self.or()?);
+                        if self.next_symbol_in(&[","]).is_none() {
+                            break;
+                        }
+                    }
+                    self.expect_symbol(")")?;
+                }
+                Ok(Expression::Call(name, arguments))
+            }
+            Some(Token::Symbol(symbol)) if symbol == "(" => {
+                self.position += 1;
+                let expression = self.or()?;
+                self.expect_symbol(")")?;
+                Ok(expression)
+            }
+            Some(Token::Symbol(symbol)) => Err(format!("unexpected '{symbol}'")),
+            None => Err("unexpected end of expression".to_owned()),
+        }
+    }
+}
+
+pub fn parse_expression(text: &str) -> Result<Expression, String> {
+    let mut parser = Parser {
+        tokens: tokenize(text)?,
+        position: 0,
+    };
+    let expression = parser.or()?;
+    if parser.position < parser.tokens.len() {
+        return Err(format!("unexpected text in '{text}'"));
+    }
+    Ok(expression)
+}
+
+/// Replaces the first word of the commands that is an alias, in blocks and procedures too, with
+/// the start of the command it stands for
+pub fn expand_aliases(statements: &mut [Statement], aliases: &BTreeMap<String, String>) {
+    for statement in statements {
+        match statement {
+            Statement::Command(command) => {
+                let mut words = command.trim_start().splitn(2, char::is_whitespace);
+                if let Some(start) = aliases.get(&words.next().unwrap_or("").to_lowercase()) {
+                    *command = format!("{start} {}", words.next().unwrap_or(""));
+                }
+            }
+            Statement::Repeat { body, .. }
+            | Statement::For { body, .. }
+            | Statement::Def { body, .. } => expand_aliases(body, aliases),
+            Statement::If {
+                body, else_body, ..
+            } => {
+                expand_aliases(body, aliases);
+                expand_aliases(else_body, aliases);
+            }
+            Statement::Include(_) | Statement::Param { .. } => {}
+        }
+    }
+}
+
+/// Parses a script into statements separated by `;`, with `{ ... }` blocks
+pub fn parse_script(text: &str) -> Result<Vec<Statement>, String> {
+    let characters: Vec<char> = text.chars().collect();
+    let mut position = 0;
+    parse_block(&characters, &mut position, false)
+}
+
+/// Whether braces are left open in `text`, which then needs more lines
+pub fn is_incomplete(text: &str) -> bool {
+    let mut depth = 0;
+    let mut in_quotes = false;
+    let mut in_comment = false;
+    for character in text.chars() {
+        match character {
+            '\n' => in_comment = false,
+            _ if in_comment => {}
+            '"' => in_quotes = !in_quotes,
+            _ if in_quotes => {}
+            '#' => in_comment = true,
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            _ => {}
+        }
+    }
+    depth > 0
+}
+
+fn parse_block(
+    characters: &[char],
+    position: &mut usize,
+    nested: bool,
+) -> Result<Vec<Statement>, String> {
+    let mut statements: Vec<Statement> = Vec::new();
+    let mut buffer = String::new();
+    loop {
+        match characters.get(*position) {
+            None => {
+                if nested {
+                    return Err("missing '}'".to_owned());
+                }
+                push_command(&mut statements, &mut buffer)?;
+                return Ok(statements);
+            }
+            Some(';') => {
+                *position += 1;
+                push_command(&mut statements, &mut buffer)?;
+            }
+            Some('{') => {
+                *position += 1;
+                let header = std::mem::take(&mut buffer);
+                let body = parse_block(characters, position, true)?;
+                statements.push(parse_compound(&header, body, characters, position)?);
+            }
+            Some('}') => {
+                if !nested {
+                    return Err("unexpected '}'".to_owned());
+                }
+                *position += 1;
+                push_command(&mut statements, &mut buffer)?;
+                return Ok(statements);
+            }
+            Some('#') => {
+                // Comments run to the end of the line
+                while characters.get(*position).is_some_and(|&c| c != '\n') {
+                    *position += 1;
+                }
+            }
+            Some('"') => {
+                // Quoted text is kept as it is
+                buffer.push('"');
+                *position += 1;
+                while let Some(&character) = characters.get(*position) {
+                    *position += 1;
+                    buffer.push(character);
+                    if character == '"' {
+                        break;
+                    }
+                }
+            }
+            Some(&character) => {
+                *position += 1;
+                buffer.push(character);
+            }
+        }
+    }
+}
+
+fn push_command(statements: &mut Vec<Statement>, buffer: &mut String) -> Result<(), String> {
+    let command = std::mem::take(buffer);
+    let command = command.trim();
+    if command.is_empty() {
+        return Ok(());
+    }
+    match command.split_once(char::is_whitespace) {
+        Some((keyword, path)) if keyword.to_lowercase() == "include" => {
+            let path = path.trim();
+            let path = path
+                .strip_prefix('"')
+                .and_then(|path| path.strip_suffix('"'))
+                .unwrap_or(path);
+            statements.push(Statement::Include(path.to_owned()));
+        }
+        Some((keyword, declaration)) if keyword.to_lowercase() == "param" => {
+            let declaration = declaration.to_lowercase();
+            let (name, default) = match declaration.split_once('=') {
+                Some((name, default)) => (name.trim(), Some(parse_expression(default)?)),
+                None => (declaration.trim(), None),
+            };
+            if !is_identifier(name) || RESERVED_NAMES.contains(&name) {
+                return Err(format!("'{name}' isn't a valid parameter name"));
+            }
+            statements.push(Statement::Param {
+                name: name.to_owned(),
+                default,
+            });
+        }
+        _ => statements.push(Statement::Command(command.to_owned())),
+    }
+    Ok(())
+}
+
+/// Skips whitespace, then consumes `word` if it comes next
+fn consume_word(characters: &[char], position: &mut usize, word: &str) -> bool {
+    let mut i = *position;
+    while i < characters.len() && characters[i].is_whitespace() {
+        i += 1;
+    }
+    let word: Vec<char> = word.chars().collect();
+    let end = i + word.len();
+    let is_word_next = characters[i..].starts_with(&word)
+        && characters
+            .get(end)
+            .is_none_or(|c| !(c.is_ascii_alphanumeric() || *c == '_'));
+    if is_word_next {
+        *position = end;
+    }
+    is_word_next
+}
+
+/// Builds the statement made of `header` and the block following it
+fn parse_compound(
+    header: &str,
+    body: Vec<Statement>,
+    characters: &[char],
+    position: &mut usize,
+) -> Result<Statement, String> {
+    let header = header.trim().to_lowercase();
+    let (keyword, rest) = header
+        .split_once(char::is_whitespace)
+        .unwrap_or((&header, ""));
+    match keyword {
+        "repeat" => Ok(Statement::Repeat {
+            count: parse_expression(rest)?,
+            body,
+        }),
+        "for" => {
+            const SYNTAX: &str = "expected 'for [name] in [start]..[end] [step [step]]'";
+            let (variable, range) = rest.split_once(" in ").ok_or(SYNTAX)?;
+            let (range, step) = match range.split_once(" step ") {
+                Some((range, step)) => (range, parse_expression(step)?),
+                None => (range, Expression::Number(1)),
+            };
+            let (start, end) = range.split_once("..").ok_or(SYNTAX)?;
+            let variable = variable.trim().to_owned();
+            if !is_identifier(&variable) || RESERVED_NAMES.contains(&variable.as_str()) {
+                return Err(format!("'{variable}' isn't a valid variable name"));
+            }
+            Ok(Statement::For {
+                variable,
+                start: parse_expression(start)?,
+                end: parse_expression(end)?,
+                step,
+                body,
+            })
+        }
+        "if" => {
+            let mut else_body: Vec<Statement> = Vec::new();
+            if consume_word(characters, position, "else") {
+                // Either a block or another `if` statement
+                let mut else_header = String::new();
+                while let Some(&character) = characters.get(*position) {
+                    *position += 1;
+                    if character == '{' {
+                        break;
+                    }
+                    else_header.push(character);
+                }
+                let block = parse_block(characters, position, true)?;
+                if else_header.trim().is_empty() {
+                    else_body = block;
+                } else {
+                    else_body = vec![parse_compound(&else_header, block, characters, position)?];
+                }
+            }
+            Ok(Statement::If {
+                condition: parse_expression(rest)?,
+                body,
+                else_body,
+            })
+        }
+        "def" => {
+            const SYNTAX: &str = "expected 'def [name]([parameters])'";
+            let (name, parameters) = rest.split_once('(').ok_or(SYNTAX)?;
+            let parameters = parameters.trim().strip_suffix(')').ok_or(SYNTAX)?;
+            let name = name.trim().to_owned();
+            let is_taken = COMMANDS.contains(&name.as_str())
+                || COMMAND_ALIASES.iter().any(|(_, alias)| *alias == name)
+                || KEYWORDS.contains(&name.as_str());
+            if !is_identifier(&name) || is_taken {
+                return Err(format!("'{name}' isn't a valid procedure name"));
+            }
+            let parameters: Vec<String> = parameters
+                .split(',')
+                .map(|parameter| parameter.trim().to_owned())
+                .filter(|parameter| !parameter.is_empty())
+                .collect();
+            for parameter in &parameters {
+                if !is_identifier(parameter) || RESERVED_NAMES.contains(&parameter.as_str()) {
+                    return Err(format!("'{parameter}' isn't a valid parameter name"));
+                }
+            }
+            Ok(Statement::Def {
+                name,
+                parameters,
+                body,
+            })
+        }
+        _ => Err(format!("unexpected block after '{header}'")),
+    }
+}
+
+/// Splits a command into arguments at whitespace outside of parentheses
+pub fn split_arguments(input: &str) -> Vec<String> {
+    let mut arguments: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0;
+    for character in input.chars() {
+        match character {
+            '(' => depth += 1,
+            ')' if depth > 0 => depth -= 1,
+            _ if character.is_whitespace() && depth == 0 => {
+                if !current.is_empty() {
+                    arguments.push(std::mem::take(&mut current));
+                }
+                continue;
+            }
+            _ => {}
+        }
+        current.push(character);
+    }
+    if !current.is_empty() {
+        arguments.push(current);
+    }
+    arguments
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut characters = text.chars();
+    matches!(characters.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && characters.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+impl Cli<'_> {
+    pub fn evaluate(&self, expression: &Expression) -> Result<i64, String> {
+        match expression {
+            Expression::Number(number) => Ok(*number),
+            Expression::Variable(name) => match name.as_str() {
+                "width" => Ok(self.image.get_width() as i64),
+                "height" => Ok(self.image.get_height() as i64),
+                _ => self
+                    .interpreter
+                    .variable(name)
+                    .ok_or(format!("unknown variable '{name}'")),
+            },
+            Expression::Unary(operator, operand) => {
+                let operand = self.evaluate(operand)?;
+                match operator.as_str() {
+                    "!" => Ok((operand == 0) as i64),
+                    _ => operand.checked_neg().ok_or("overflow".to_owned()),
+                }
+            }
+            Expression::Binary(operator, left, right) => {
+                let left = self.evaluate(left)?;
+                // `&&` and `||` only evaluate their right operand if needed
+                match operator.as_str() {
+                    "&&" if left == 0 => return Ok(0),
+                    "||" if left != 0 => return Ok(1),
+                    _ => {}
+                }
+                let right = self.evaluate(right)?;
+                if (operator == "/" || operator == "%") && right == 0 {
+                    return Err("division by zero".to_owned());
+                }
+                match operator.as_str() {
+                    "+" => left.checked_add(right),
+                    "-" => left.checked_sub(right),
+                    "*" => left.checked_mul(right),
+                    "/" => left.checked_div(right),
+                    "%" => left.checked_rem(right),
+                    "==" => Some((left == right) as i64),
+                    "!=" => Some((left != right) as i64),
+                    "<" => Some((left < right) as i64),
+                    "<=" => Some((left <= right) as i64),
+                    ">" => Some((left > right) as i64),
+                    ">=" => Some((left >= right) as i64),
+                    _ => Some((right != 0) as i64),
+                }
+                .ok_or("overflow".to_owned())
+            }
+            Expression::Call(name, arguments) => {
+                let values = arguments
+                    .iter()
+                    .map(|argument| self.evaluate(argument))
+                    .collect::<Result<Vec<i64>, String>>()?;
+                let result = match name.as_str() {
+                    "min" => values.iter().min(),
+                    "max" => values.iter().max(),
+                    _ => return Err(format!("unknown function '{name}'")),
+                };
+                result
+                    .copied()
+                    .ok_or(format!("'{name}' needs at least one argument"))
+            }
+        }
+    }
+    /// Runs statements, stopping at the first error
+    pub fn execute(&mut self, statements: &[Statement]) -> Result<(), String> {
+        for statement in statements {
+            match statement {
+                Statement::Command(command) => {
+                    let name = command.split_whitespace().next().unwrap_or("");
+                    if self
+                        .interpreter
+                        .procedures
+                        .contains_key(&name.to_lowercase())
+                    {
+                        self.call_procedure(command)?;
+                    } else {
+                        self.parse_command(command.to_owned());
+                    }
+                    let error = self.error.take();
+                    if let Some(message) = self.failure.take() {
+                        return Err(format!("assertion failed: {message}"));
+                    }
+                    if let (Some(message), true) = (error, self.batch) {
+                        return Err(message);
+                    }
+                }
+                Statement::Repeat { count, body } => {
+                    for _ in 0..self.evaluate(count)? {
+                        self.execute(body)?;
+                    }
+                }
+                Statement::For {
+                    variable,
+                    start,
+                    end,
+                    step,
+                    body,
+                } => {
+                    let start = self.evaluate(start)?;
+                    let end = self.evaluate(end)?;
+                    let step = self.evaluate(step)?;
+                    if step == 0 {
+                        return Err("the step of a loop can't be 0".to_owned());
+                    }
+                    // The loop variable is restored afterwards
+                    let previous_value = self.interpreter.scope().get(variable).copied();
+                    let mut value = start;
+                    while (step > 0 && value < end) || (step < 0 && value > end) {
+                        self.interpreter.scope().insert(variable.to_owned(), value);
+                        self.execute(body)?;
+                        // Past the largest or smallest number, the value is past the end too
+                        match value.checked_add(step) {
+                            Some(next_value) => value = next_value,
+                            None => break,
+                        }
+                    }
+                    match previous_value {
+                        Some(previous_value) => self
+                            .interpreter
+                            .scope()
+                            .insert(variable.to_owned(), previous_value),
+                        None => self.interpreter.scope().remove(variable),
+                    };
+                }
+                Statement::If {
+                    condition,
+                    body,
+                    else_body,
+                } => {
+                    if self.evaluate(condition)? != 0 {
+                        self.execute(body)?;
+                    } else {
+                        self.execute(else_body)?;
+                    }
+                }
+                Statement::Def {
+                    name,
+                    parameters,
+                    body,
+                } => {
+                    let procedure = Procedure {
+                        parameters: parameters.to_owned(),
+                        body: body.to_owned(),
+                    };
+                    self.interpreter
+                        .procedures
+                        .insert(name.to_owned(), procedure);
+                }
+                Statement::Include(path) => {
+                    let path = self.resolve_include(path)?;
+                    self.run_file(&path)?;
+                }
+                Statement::Param { name, default } => {
+                    let value = match (self.interpreter.arguments.get(name), default) {
+                        (Some(&value), _) => value,
+                        (None, Some(default)) => self.evaluate(default)?,
+                        (None, None) => return Err(format!("missing parameter '{name}'")),
+                    };
+                    self.interpreter.scope().insert(name.to_owned(), value);
+                }
+            }
+        }
+        Ok(())
+    }
+    /// Runs a procedure with its parameters bound to the arguments of `command`, colors being 1
+    /// for `t` and 0 for `f`
+    fn call_procedure(&mut self, command: &str) -> Result<(), String> {
+        let arguments = split_arguments(&self.expand_expressions(command)?);
+        let name = arguments[0].to_lowercase();
+        let procedure = self.interpreter.procedures[&name].clone();
+        if arguments.len() - 1 != procedure.parameters.len() {
+            return Err(format!(
+                "'{name}' takes {} arguments, not {}",
+                procedure.parameters.len(),
+                arguments.len() - 1
+            ));
+        }
+        if self.interpreter.frames.len() >= MAX_CALL_DEPTH {
+            return Err(format!("too many nested calls of '{name}'"));
+        }
+        let mut frame: HashMap<String, i64> = HashMap::new();
+        for (parameter, argument) in procedure.parameters.iter().zip(&arguments[1..]) {
+            let value = match crate::parse_color(argument) {
+                Some(color) => color as i64,
+                None => argument
+                    .parse()
+                    .map_err(|_| format!("'{name}' expects numbers or colors, not '{argument}'"))?,
+            };
+            frame.insert(parameter.to_owned(), value);
+        }
+        self.interpreter.frames.push(frame);
+        let result = self.execute(&procedure.body);
+        self.interpreter.frames.pop();
+        result
+    }
+    /// Returns `path` relative to the directory of the running script, or else as it is
+    pub fn resolve_path(&self, path: &str) -> PathBuf {
+        match self.interpreter.files.last().and_then(|file| file.parent()) {
+            Some(directory) => directory.join(path),
+            None => PathBuf::from(path),
+        }
+    }
+    /// Finds an included script next to the current one, or else in the include paths
+    fn resolve_include(&self, path: &str) -> Result<PathBuf, String> {
+        if Path::new(path).is_absolute() {
+            return Ok(PathBuf::from(path));
+        }
+        std::iter::once(self.resolve_path(path))
+            .chain(
+                self.interpreter
+                    .include_paths
+                    .iter()
+                    .map(|directory| directory.join(path)),
+            )
+            .find(|candidate| candidate.is_file())
+            .ok_or(format!("can't find '{path}' to include"))
+    }
+    /// Runs the script at `path`, which mustn't be running already
+    pub fn run_file(&mut self, path: &Path) -> Result<(), String> {
+        let canonical_path = path
+            .canonicalize()
+            .map_err(|e| format!("failed to read '{}': {e}", path.display()))?;
+        if self.interpreter.files.contains(&canonical_path) {
+            let cycle = self
+                .interpreter
+                .files
+                .iter()
+                .skip_while(|file| **file != canonical_path)
+                .chain(std::iter::once(&canonical_path))
+                .map(|file| file.display().to_string())
+                .collect::<Vec<String>>();
+            return Err(format!("include cycle: {}", cycle.join(" -> ")));
+        }
+        let text = std::fs::read_to_string(&canonical_path)
+            .map_err(|e| format!("failed to read '{}': {e}", path.display()))?;
+        let statements =
+            parse_script(&text).map_err(|message| format!("{}: {message}", path.display()))?;
+        if self.interpreter.files.is_empty() {
+            self.bind_arguments(&statements)?;
+        }
+        self.interpreter.files.push(canonical_path);
+        let result = self.execute(&statements);
+        self.interpreter.files.pop();
+        result
+    }
+    /// Matches the command line arguments with the parameters declared by a script
+    fn bind_arguments(&mut self, statements: &[Statement]) -> Result<(), String> {
+        let parameters: Vec<(&String, bool)> = statements
+            .iter()
+            .filter_map(|statement| match statement {
+                Statement::Param { name, default } => Some((name, default.is_some())),
+                _ => None,
+            })
+            .collect();
+        let positional_arguments = std::mem::take(&mut self.interpreter.positional_arguments);
+        if positional_arguments.len() > parameters.len() {
+            return Err(format!(
+                "the script takes {} parameters, not {}",
+                parameters.len(),
+                positional_arguments.len()
+            ));
+        }
+        for ((name, _), value) in parameters.iter().zip(positional_arguments) {
+            if self.interpreter.arguments.contains_key(*name) {
+                return Err(format!("parameter '{name}' is given twice"));
+            }
+            self.interpreter.arguments.insert(name.to_string(), value);
+        }
+        let mut unknown: Vec<&String> = self
+            .interpreter
+            .arguments
+            .keys()
+            .filter(|name| !parameters.iter().any(|(parameter, _)| parameter == name))
+            .collect();
+        if !unknown.is_empty() {
+            unknown.sort();
+            let unknown: Vec<String> = unknown.iter().map(|name| name.to_string()).collect();
+            return Err(format!("unknown parameters: {}", unknown.join(", ")));
+        }
+        let missing: Vec<String> = parameters
+            .iter()
+            .filter(|(name, has_default)| {
+                !has_default && !self.interpreter.arguments.contains_key(*name)
+            })
+            .map(|(name, _)| name.to_string())
+            .collect();
+        if !missing.is_empty() {
+            return Err(format!("missing parameters: {}", missing.join(", ")));
+        }
+        Ok(())
+    }
+    /// Replaces the number arguments of a command that are expressions by their value, and the
+    /// color arguments that are expressions by `t` if their value isn't 0 or else `f`
+    ///
+    /// Other arguments, like names and paths, are left as they are.
+    pub fn expand_expressions(&self, input: &str) -> Result<String, String> {
+        let arguments = split_arguments(input);
+        let lowercase_arguments: Vec<String> = arguments
+            .iter()
+            .map(|argument| argument.to_lowercase())
+            .collect();
+        let lowercase_arguments: Vec<&str> =
+            lowercase_arguments.iter().map(String::as_str).collect();
+        let mut expanded: Vec<String> = Vec::new();
+        for (i, argument) in arguments.iter().enumerate() {
+            let lowercase_argument = argument.to_lowercase();
+            let kind = argument_kind(&lowercase_arguments, i);
+            let is_literal = kind == Argument::Text
+                || lowercase_argument.starts_with("--")
+                || ["t", "f", "true", "false"].contains(&lowercase_argument.as_str())
+                || (is_identifier(&lowercase_argument)
+                    && self.interpreter.variable(&lowercase_argument).is_none()
+                    && lowercase_argument != "width"
+                    && lowercase_argument != "height");
+            if is_literal {
+                expanded.push(argument.to_owned());
+                continue;
+            }
+            match parse_expression(&lowercase_argument) {
+                Ok(expression) => {
+                    let value = self.evaluate(&expression)?;
+                    expanded.push(match kind {
+                        Argument::Color if value != 0 => "t".to_owned(),
+                        Argument::Color => "f".to_owned(),
+                        _ => value.to_string(),
+                    });
+                }
+                // Not an expression
+                Err(_) => expanded.push(argument.to_owned()),
+            }
+        }
+        Ok(expanded.join(" "))
+    }
+    /// Executes `let [name] = [expression]`
+    pub fn assign(&mut self, assignment: &str) -> Result<(), String> {
+        let Some((name, expression)) = assignment.split_once('=') else {
+            return Err("expected '='".to_owned());
+        };
+        let name = name.trim().to_lowercase();
+        if !is_identifier(&name) || RESERVED_NAMES.contains(&name.as_str()) {
+            return Err(format!("'{name}' isn't a valid variable name"));
+        }
+        let value = self.evaluate(&parse_expression(&expression.to_lowercase())?)?;
+        self.interpreter.scope().insert(name, value);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_incomplete, parse_expression, parse_script, MAX_CALL_DEPTH};
+    use crate::{Cli, Image};
+    use std::path::{Path, PathBuf};
+
+    /// Runs `test` with an interpreter in batch mode, drawing on a 10 * 10 image
+    fn with_cli<T>(test: impl FnOnce(&mut Cli) -> T) -> T {
+        let mut image = Image::new(10, 10, false);
+        let mut cli = Cli::new(String::new(), &mut image);
+        cli.batch = true;
+        test(&mut cli)
+    }
+
+    fn run(cli: &mut Cli, script: &str) -> Result<(), String> {
+        parse_script(script).and_then(|statements| cli.execute(&statements))
+    }
+
+    /// Evaluates an expression where `x` is 5
+    fn evaluate(text: &str) -> Result<i64, String> {
+        with_cli(|cli| {
+            cli.assign("x = 5")?;
+            cli.evaluate(&parse_expression(text)?)
+        })
+    }
+
+    #[test]
+    fn operator_precedence() {
+        for (text, value) in [
+            ("1 + 2 * 3", 7),
+            ("(1 + 2) * 3", 9),
+            ("10 - 4 - 3", 3),
+            ("17 % 5 * 2", 4),
+            ("7 - -2", 9),
+            ("-x * 2", -10),
+            ("!0 + 1", 2),
+            ("!(x > 3)", 0),
+            ("2 < 3 == 1", 1),
+            ("1 + 1 == 2 && 3 < 2 || 1", 1),
+            ("1 || 0 && 0", 1),
+            ("min(x, 3) + max(1, 2, x) * 2", 13),
+            ("-7 / 2", -3),
+            ("-7 % 3", -1),
+            ("width - height + x", 5),
+            // `&&` and `||` don't evaluate their right operand if not needed
+            ("0 && 1 / 0", 0),
+            ("1 || 1 / 0", 1),
+        ] {
+            assert_eq!(evaluate(text), Ok(value), "{text}");
+        }
+    }
+
+    #[test]
+    fn evaluation_errors() {
+        for (text, message) in [
+            ("9223372036854775807 + 1", "overflow"),
+            ("-9223372036854775807 - 2", "overflow"),
+            ("4611686018427387904 * 2", "overflow"),
+            ("-(-9223372036854775807 - 1)", "overflow"),
+            ("(-9223372036854775807 - 1) / -1", "overflow"),
+            ("x / 0", "division by zero"),
+            ("x % (x - 5)", "division by zero"),
+            (
+                "99999999999999999999",
+                "number '99999999999999999999' is too large",
+            ),
+            ("y + 1", "unknown variable 'y'"),
+            ("sqrt(4)", "unknown function 'sqrt'"),
+            ("max()", "'max' needs at least one argument"),
+            ("(1 + 2", "expected ')'"),
+            ("1 +", "unexpected end of expression"),
+            ("1 2", "unexpected text in '1 2'"),
+            ("2 ^ 3", "unexpected character '^'"),
+        ] {
+            assert_eq!(evaluate(text), Err(message.to_owned()), "{text}");
+        }
+    }
+
+    #[test]
+    fn nested_loops() {
+        with_cli(|cli| {
+            let script = "
+                let n = 0; let m = 0;
+                repeat 3 {
+                    for i in 0..4 { let n = n + i }
+                    for i in 10..0 step -5 { let n = n + i }
+                    repeat 2 { let m = m + 1 }
+                }
+                repeat -1 { let m = 100 }
+                for i in 0..0 { let m = 100 }";
+            assert_eq!(run(cli, script), Ok(()));
+            // The loop variable doesn't outlive its loop
+            assert_eq!(
+                cli.interpreter.visible_variables(),
+                [("m".to_owned(), 6), ("n".to_owned(), 63)]
+            );
+            assert_eq!(
+                run(cli, "for i in 0..3 step 0 { }"),
+                Err("the step of a loop can't be 0".to_owned())
+            );
+        });
+    }
+
+    #[test]
+    fn else_if_chains() {
+        with_cli(|cli| {
+            let script = "
+                let a = 0; let b = 0; let c = 0; let d = 0;
+                for i in 0..6 {
+                    if i == 0 {
+                        let a = a + 1
+                    } else if i < 3 {
+                        let b = b + 1
+                    } else if i == 3 {
+                        let c = c + 1
+                    } else {
+                        let d = d + 1
+                    }
+                }";
+            assert_eq!(run(cli, script), Ok(()));
+            assert_eq!(
+                cli.interpreter.visible_variables(),
+                [
+                    ("a".to_owned(), 1),
+                    ("b".to_owned(), 2),
+                    ("c".to_owned(), 1),
+                    ("d".to_owned(), 2)
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn unterminated_blocks() {
+        for (script, message) in [
+            ("repeat 2 { dr 0 0 1 1 t;", "missing '}'"),
+            ("if 1 { } else {", "missing '}'"),
+            ("def p() { repeat 2 { }", "missing '}'"),
+            ("}", "unexpected '}'"),
+            ("repeat 2 { let a = 1 } }", "unexpected '}'"),
+            ("dr 0 0 1 1 t { }", "unexpected block after 'dr 0 0 1 1 t'"),
+        ] {
+            assert_eq!(parse_script(script), Err(message.to_owned()), "{script}");
+        }
+        assert!(is_incomplete("repeat 2 {\n    dr 0 0 1 1 t;"));
+        assert!(!is_incomplete("repeat 2 { }"));
+        // Braces in text and comments don't count
+        assert!(!is_incomplete("include \"{.ipcli\"; # {"));
+    }
+
+    #[test]
+    fn procedure_arguments() {
+        with_cli(|cli| {
+            assert_eq!(run(cli, "def dot(x, y) { write x y t }"), Ok(()));
+            assert_eq!(
+                run(cli, "dot 1"),
+                Err("'dot' takes 2 arguments, not 1".to_owned())
+            );
+            assert_eq!(
+                run(cli, "dot 1 2 3"),
+                Err("'dot' takes 2 arguments, not 3".to_owned())
+            );
+            assert_eq!(
+                run(cli, "dot 1 a"),
+                Err("'dot' expects numbers or colors, not 'a'".to_owned())
+            );
+            assert_eq!(run(cli, "let y = 4; dot (y - 3) (y + 1)"), Ok(()));
+            assert!(cli.image.grid[5][1]);
+            assert_eq!(cli.image.count(true), 1);
+        });
+    }
+
+    #[test]
+    fn procedure_variables_are_local() {
+        with_cli(|cli| {
+            let script = "
+                let x = 1; let g = 2;
+                def shift(x) { let y = x + g; let x = y; write x 0 t; for i in 0..2 { } }
+                shift 3";
+            assert_eq!(run(cli, script), Ok(()));
+            // Globals are visible in procedures, which don't change them
+            assert!(cli.image.grid[0][5]);
+            assert_eq!(
+                cli.interpreter.visible_variables(),
+                [("g".to_owned(), 2), ("x".to_owned(), 1)]
+            );
+            // The variables of the caller aren't visible
+            let script = "def inner() { write (a) 0 t } def outer(a) { inner } outer 1";
+            assert_eq!(run(cli, script), Err("unknown variable 'a'".to_owned()));
+            assert!(cli.interpreter.frames.is_empty());
+        });
+    }
+
+    #[test]
+    fn recursion_is_limited() {
+        with_cli(|cli| {
+            assert_eq!(
+                run(cli, "def down(n) { if n > 0 { down (n - 1) } }"),
+                Ok(())
+            );
+            assert_eq!(run(cli, &format!("down {}", MAX_CALL_DEPTH - 1)), Ok(()));
+            assert_eq!(
+                run(cli, &format!("down {MAX_CALL_DEPTH}")),
+                Err("too many nested calls of 'down'".to_owned())
+            );
+            assert!(cli.interpreter.frames.is_empty());
+        });
+    }
+
+    #[test]
+    fn colors_as_procedure_arguments() {
+        with_cli(|cli| {
+            let script = "
+                def dot(x, c) { write x 0 c; write x 1 (!c) }
+                dot 1 t; dot 2 f; dot 3 true; dot 4 1; dot 5 0; dot 6 (3 > 2)";
+            assert_eq!(run(cli, script), Ok(()));
+            assert_eq!(
+                cli.image.grid[0][1..7],
+                [true, false, true, true, false, true]
+            );
+            assert_eq!(
+                cli.image.grid[1][1..7],
+                [false, true, false, false, true, false]
+            );
+        });
+    }
+
+    /// Returns the path of a file of `tests/fixtures`
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    #[test]
+    fn includes_are_relative_to_the_including_script() {
+        with_cli(|cli| {
+            // The tests run from the root of the repository
+            assert_eq!(cli.run_file(&fixture("include.ipcli")), Ok(()));
+            assert!(cli.image.grid[4][3]);
+            assert!(cli.interpreter.files.is_empty());
+            assert_eq!(
+                run(cli, "include \"dot.ipcli\""),
+                Err("can't find 'dot.ipcli' to include".to_owned())
+            );
+            // The include paths are searched next
+            cli.interpreter.include_paths = vec![fixture("include")];
+            assert_eq!(run(cli, "include \"dot.ipcli\""), Ok(()));
+        });
+    }
+
+    #[test]
+    fn include_cycles_are_errors() {
+        with_cli(|cli| {
+            let first = fixture("include/cycle_a.ipcli").canonicalize().unwrap();
+            let second = fixture("include/cycle_b.ipcli").canonicalize().unwrap();
+            assert_eq!(
+                cli.run_file(&first),
+                Err(format!(
+                    "include cycle: {} -> {} -> {}",
+                    first.display(),
+                    second.display(),
+                    first.display()
+                ))
+            );
+            // Both scripts ran until the cycle
+            assert!(cli.image.grid[0][0] && cli.image.grid[0][1]);
+            assert!(cli.interpreter.files.is_empty());
+        });
+    }
+
+    /// Runs a script given the arguments of `--set` and the positional ones, like `-s` does
+    fn run_with_arguments(
+        cli: &mut Cli,
+        script: &str,
+        arguments: &[(&str, i64)],
+        positional_arguments: &[i64],
+    ) -> Result<(), String> {
+        cli.interpreter.arguments = arguments
+            .iter()
+            .map(|&(name, value)| (name.to_owned(), value))
+            .collect();
+        cli.interpreter.positional_arguments = positional_arguments.to_vec();
+        let statements = parse_script(script)?;
+        cli.bind_arguments(&statements)?;
+        cli.execute(&statements)
+    }
+
+    #[test]
+    fn parameters() {
+        const SCRIPT: &str = "param size; param color = size > 2; param step = 1 + 1";
+        for (arguments, positional_arguments, expected) in [
+            (&[("size", 3)][..], &[][..], Ok(vec![3, 1, 2])),
+            (&[("size", 1), ("step", 5)], &[], Ok(vec![1, 0, 5])),
+            (&[], &[4, 0], Ok(vec![4, 0, 2])),
+            (&[("step", 7)], &[4], Ok(vec![4, 1, 7])),
+            (&[], &[], Err("missing parameters: size")),
+            (&[("step", 7)], &[], Err("missing parameters: size")),
+            (
+                &[("size", 1), ("sise", 2), ("colour", 3)],
+                &[],
+                Err("unknown parameters: colour, sise"),
+            ),
+            (&[("size", 1)], &[2], Err("parameter 'size' is given twice")),
+            (
+                &[],
+                &[1, 2, 3, 4],
+                Err("the script takes 3 parameters, not 4"),
+            ),
+        ] {
+            with_cli(|cli| {
+                let result =
+                    run_with_arguments(cli, SCRIPT, arguments, positional_arguments).map(|()| {
+                        ["size", "color", "step"]
+                            .map(|name| cli.interpreter.variable(name).unwrap())
+                            .to_vec()
+                    });
+                assert_eq!(
+                    result,
+                    expected.map_err(str::to_owned),
+                    "{arguments:?} {positional_arguments:?}"
+                );
+            });
+        }
+    }
+}
@@ -0,0 +1,275 @@
+//! Vector export of the image to SVG
+
+use crate::Image;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Mode {
+    /// Rectangles covering the set pixels exactly
+    Pixel,
+    /// Contours of the set regions, simplified within `tolerance` pixels if given
+    Traced { tolerance: Option<f32> },
+}
+
+impl Mode {
+    pub fn from_arguments(arguments: &[&str]) -> Option<Mode> {
+        match arguments {
+            [] | ["pixel"] => Some(Mode::Pixel),
+            ["traced"] => Some(Mode::Traced { tolerance: None }),
+            ["traced", tolerance] => match tolerance.parse::<f32>() {
+                Ok(tolerance) if tolerance >= 0.0 => Some(Mode::Traced {
+                    tolerance: Some(tolerance),
+                }),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+impl Image {
+    /// Returns an SVG document drawing the `true` pixels in black
+    pub fn to_svg(&self, mode: Mode) -> String {
+        let path_data = match mode {
+            Mode::Pixel => self.rectangles_path_data(),
+            Mode::Traced { tolerance } => self.contours_path_data(tolerance),
+        };
+        let width = self.get_width();
+        let height = self.get_height();
+        format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" viewBox=\"0 0 {width} {height}\">\n\
+             <path fill=\"black\" fill-rule=\"evenodd\" d=\"{path_data}\"/>\n\
+             </svg>\n"
+        )
+    }
+    pub fn export_svg(&self, path: &str, mode: Mode) -> Result<(), String> {
+        std::fs::write(path, self.to_svg(mode))
+            .map_err(|e| format!("failed to write '{path}': {e}"))
+    }
+    /// Merges horizontal runs of pixels, then identical runs on consecutive rows, into rectangles
+    fn rectangles_path_data(&self) -> String {
+        // Rectangles still growing downwards, as (x, y, w, h)
+        let mut open: Vec<(usize, usize, usize, usize)> = Vec::new();
+        let mut closed: Vec<(usize, usize, usize, usize)> = Vec::new();
+        for (y, row) in self.grid.iter().enumerate() {
+            let mut runs: Vec<(usize, usize)> = Vec::new();
+            let mut x = 0;
+            while x < row.len() {
+                if row[x] {
+                    let start = x;
+                    while x < row.len() && row[x] {
+                        x += 1;
+                    }
+                    runs.push((start, x - start));
+                } else {
+                    x += 1;
+                }
+            }
+            let mut still_open: Vec<(usize, usize, usize, usize)> = Vec::new();
+            for rectangle in open {
+                if let Some(i) = runs
+                    .iter()
+                    .position(|&(x, w)| x == rectangle.0 && w == rectangle.2)
+                {
+                    runs.remove(i);
+                    still_open.push((rectangle.0, rectangle.1, rectangle.2, rectangle.3 + 1));
+                } else {
+                    closed.push(rectangle);
+                }
+            }
+            still_open.extend(runs.iter().map(|&(x, w)| (x, y, w, 1)));
+            open = still_open;
+        }
+        closed.extend(open);
+        closed.sort_by_key(|&(x, y, _, _)| (y, x));
+        closed
+            .iter()
+            .map(|(x, y, w, h)| format!("M{x} {y}h{w}v{h}h-{w}z"))
+            .collect::<Vec<String>>()
+            .join("")
+    }
+    /// Follows the edges between set and unset pixels, holes included
+    fn contours_path_data(&self, tolerance: Option<f32>) -> String {
+        let mut path_data = String::new();
+        for contour in self.edge_contours() {
+            let points = match tolerance {
+                Some(tolerance) => simplify_closed(&contour, tolerance),
+                None => contour,
+            };
+            for (i, (x, y)) in points.iter().enumerate() {
+                let command = if i == 0 { "M" } else { "L" };
+                path_data += &format!("{command}{} {}", format_number(*x), format_number(*y));
+            }
+            path_data += "z";
+        }
+        path_data
+    }
+    /// Chains the pixel edges separating set pixels from unset ones into closed polygons
+    ///
+    /// Outer boundaries go clockwise and holes counter-clockwise; only the corners are kept.
+    fn edge_contours(&self) -> Vec<Vec<(f32, f32)>> {
+        let width = self.get_width() as i32;
+        let height = self.get_height() as i32;
+        let is_set = |x: i32, y: i32| {
+            x >= 0 && y >= 0 && x < width && y < height && self.grid[y as usize][x as usize]
+        };
+        // Directed edges between pixel corners, keeping set pixels on their right
+        let mut edges: std::collections::BTreeMap<(i32, i32), Vec<(i32, i32)>> =
+            std::collections::BTreeMap::new();
+        for y in 0..height {
+            for x in 0..width {
+                if !is_set(x, y) {
+                    continue;
+                }
+                let sides = [
+                    (!is_set(x, y - 1), (x, y), (x + 1, y)),
+                    (!is_set(x + 1, y), (x + 1, y), (x + 1, y + 1)),
+                    (!is_set(x, y + 1), (x + 1, y + 1), (x, y + 1)),
+                    (!is_set(x - 1, y), (x, y + 1), (x, y)),
+                ];
+                for (is_boundary, from, to) in sides {
+                    if is_boundary {
+                        edges.entry(from).or_default().push(to);
+                    }
+                }
+            }
+        }
+        // With the even-odd rule, any way of chaining the edges gives the same shape
+        let mut contours: Vec<Vec<(f32, f32)>> = Vec::new();
+        while let Some((&start, _)) = edges.iter().next() {
+            let mut corners: Vec<(i32, i32)> = vec![start];
+            let mut current = start;
+            loop {
+                let targets = edges.get_mut(&current).unwrap();
+                let next = targets.pop().unwrap();
+                if targets.is_empty() {
+                    edges.remove(&current);
+                }
+                if next == start {
+                    break;
+                }
+                corners.push(next);
+                current = next;
+            }
+            contours.push(
+                remove_collinear(&corners)
+                    .iter()
+                    .map(|&(x, y)| (x as f32, y as f32))
+                    .collect(),
+            );
+        }
+        contours
+    }
+}
+
+/// Removes the points in the middle of straight segments of a closed polygon
+fn remove_collinear(points: &[(i32, i32)]) -> Vec<(i32, i32)> {
+    let length = points.len();
+    (0..length)
+        .filter(|&i| {
+            let (px, py) = points[(i + length - 1) % length];
+            let (x, y) = points[i];
+            let (nx, ny) = points[(i + 1) % length];
+            (x - px) * (ny - y) != (y - py) * (nx - x)
+        })
+        .map(|i| points[i])
+        .collect()
+}
+
+/// Simplifies a closed polygon with the Ramer–Douglas–Peucker algorithm
+fn simplify_closed(points: &[(f32, f32)], tolerance: f32) -> Vec<(f32, f32)> {
+    if points.len() < 4 {
+        return points.to_vec();
+    }
+    // Split the polygon at its first point and the point farthest from it
+    let distance = |(x, y): (f32, f32)| (x - points[0].0).hypot(y - points[0].1);
+    let farthest = (1..points.len())
+        .max_by(|&a, &b| distance(points[a]).total_cmp(&distance(points[b])))
+        .unwrap();
+    let mut first_half = points[..=farthest].to_vec();
+    let mut second_half = points[farthest..].to_vec();
+    second_half.push(points[0]);
+    first_half = simplify_open(&first_half, tolerance);
+    second_half = simplify_open(&second_half, tolerance);
+    first_half.pop();
+    second_half.pop();
+    first_half.extend(second_half);
+    if first_half.len() < 3 {
+        // Don't collapse small shapes into lines
+        return points.to_vec();
+    }
+    first_half
+}
+
+fn simplify_open(points: &[(f32, f32)], tolerance: f32) -> Vec<(f32, f32)> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+    let (ax, ay) = points[0];
+    let (bx, by) = points[points.len() - 1];
+    let length = (bx - ax).hypot(by - ay);
+    let distance = |&(x, y): &(f32, f32)| {
+        if length == 0.0 {
+            (x - ax).hypot(y - ay)
+        } else {
+            ((bx - ax) * (ay - y) - (ax - x) * (by - ay)).abs() / length
+        }
+    };
+    let (index, max_distance) = points[1..points.len() - 1]
+        .iter()
+        .enumerate()
+        .map(|(i, point)| (i + 1, distance(point)))
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap();
+    if max_distance <= tolerance {
+        return vec![points[0], points[points.len() - 1]];
+    }
+    let mut simplified = simplify_open(&points[..=index], tolerance);
+    simplified.pop();
+    simplified.extend(simplify_open(&points[index..], tolerance));
+    simplified
+}
+
+/// Formats a coordinate without trailing zeros
+fn format_number(number: f32) -> String {
+    let formatted = format!("{number:.2}");
+    formatted
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mode;
+    use crate::Image;
+
+    #[test]
+    fn pixel_mode() {
+        let image = Image::from_rows(&["##.#", "##.#", "...#", "..##"]);
+        assert_eq!(
+            image.to_svg(Mode::Pixel),
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"4\" height=\"4\" viewBox=\"0 0 4 4\">\n\
+             <path fill=\"black\" fill-rule=\"evenodd\" d=\"M0 0h2v2h-2zM3 0h1v3h-1zM2 3h2v1h-2z\"/>\n\
+             </svg>\n"
+        );
+    }
+
+    #[test]
+    fn traced_mode() {
+        // Holes are separate contours, filled out by the even-odd rule
+        let ring = Image::from_rows(&["###", "#.#", "###"]);
+        assert_eq!(
+            ring.to_svg(Mode::Traced { tolerance: None }),
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"3\" height=\"3\" viewBox=\"0 0 3 3\">\n\
+             <path fill=\"black\" fill-rule=\"evenodd\" d=\"M0 0L3 0L3 3L0 3zM1 1L1 2L2 2L2 1z\"/>\n\
+             </svg>\n"
+        );
+        // Simplifying turns the steps of a diagonal edge into a straight line
+        let steps = Image::from_rows(&["#...", "##..", "###.", "####"]);
+        assert_eq!(
+            steps.contours_path_data(None),
+            "M0 0L1 0L1 1L2 1L2 2L3 2L3 3L4 3L4 4L0 4z"
+        );
+        assert_eq!(steps.contours_path_data(Some(1.0)), "M0 0L4 4L0 4z");
+    }
+}
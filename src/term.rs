@@ -0,0 +1,136 @@
+//! Terminal size detection, raw mode and color support
+
+use std::io::IsTerminal;
+
+/// Size used when the terminal can't be queried
+pub const DEFAULT_SIZE: (usize, usize) = (80, 24);
+
+/// Returns the number of columns and rows of the terminal
+///
+/// Asks `stty` about the controlling terminal, then falls back to the `COLUMNS` and `LINES`
+/// environment variables, then to 80 * 24.
+pub fn size() -> (usize, usize) {
+    stty_size()
+        .or_else(environment_size)
+        .unwrap_or(DEFAULT_SIZE)
+}
+
+/// Colors a terminal can show
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColorSupport {
+    None,
+    /// The 16 basic colors
+    Basic,
+    /// The 256 colors of xterm
+    Indexed,
+    /// 24-bit colors
+    TrueColor,
+}
+
+/// Returns the colors supported by an output, none if it isn't a terminal or `NO_COLOR` is set
+///
+/// Relies on the `COLORTERM` and `TERM` environment variables.
+pub fn color_support(is_terminal: bool) -> ColorSupport {
+    let variable = |name: &str| std::env::var(name).unwrap_or_default();
+    if !is_terminal || !variable("NO_COLOR").is_empty() || variable("TERM") == "dumb" {
+        ColorSupport::None
+    } else if ["truecolor", "24bit"].contains(&variable("COLORTERM").as_str()) {
+        ColorSupport::TrueColor
+    } else if variable("TERM").contains("256color") {
+        ColorSupport::Indexed
+    } else {
+        ColorSupport::Basic
+    }
+}
+
+/// Returns `text` in yellow if the standard error supports colors
+pub fn warning(text: &str) -> String {
+    colored(text, "\x1b[33m")
+}
+
+/// Returns `text` in red if the standard error supports colors
+pub fn error(text: &str) -> String {
+    colored(text, "\x1b[31m")
+}
+
+/// Returns `text` as is if the standard output supports colors, without its bold and reset
+/// escape sequences otherwise
+pub fn styled(text: &str) -> String {
+    if color_support(std::io::stdout().is_terminal()) == ColorSupport::None {
+        text.replace("\x1b[1m", "").replace("\x1b[0m", "")
+    } else {
+        text.to_owned()
+    }
+}
+
+fn colored(text: &str, color: &str) -> String {
+    if color_support(std::io::stderr().is_terminal()) == ColorSupport::None {
+        text.to_owned()
+    } else {
+        format!("{color}{text}\x1b[0m")
+    }
+}
+
+/// Keeps the terminal in raw mode, without echo nor line buffering, until dropped
+pub struct RawMode {
+    saved_settings: String,
+}
+
+impl RawMode {
+    pub fn enable() -> Result<RawMode, String> {
+        let saved_settings = stty(&["-g"])?;
+        stty(&["raw", "-echo"])?;
+        Ok(RawMode { saved_settings })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = stty(&[self.saved_settings.trim()]);
+    }
+}
+
+/// Runs `stty` on the controlling terminal, returning its output
+fn stty(arguments: &[&str]) -> Result<String, String> {
+    let tty =
+        std::fs::File::open("/dev/tty").map_err(|e| format!("failed to open the terminal: {e}"))?;
+    let output = std::process::Command::new("stty")
+        .args(arguments)
+        .stdin(tty)
+        .output()
+        .map_err(|e| format!("failed to run stty: {e}"))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_owned());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn stty_size() -> Option<(usize, usize)> {
+    let tty = std::fs::File::open("/dev/tty").ok()?;
+    let output = std::process::Command::new("stty")
+        .arg("size")
+        .stdin(tty)
+        .stderr(std::process::Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let text = String::from_utf8(output.stdout).ok()?;
+    let mut numbers = text.split_whitespace().map(|number| number.parse().ok());
+    let rows: usize = numbers.next()??;
+    let columns: usize = numbers.next()??;
+    (rows > 0 && columns > 0).then_some((columns, rows))
+}
+
+fn environment_size() -> Option<(usize, usize)> {
+    let variable = |name: &str| -> Option<usize> {
+        std::env::var(name)
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+            .filter(|&n| n > 0)
+    };
+    Some((variable("COLUMNS")?, variable("LINES")?))
+}
@@ -0,0 +1,284 @@
+//! Glyphs, colors and frame of the rendered image
+
+use crate::term::ColorSupport;
+
+/// Names of the 16 basic colors, by ANSI number
+const BASIC_COLOR_NAMES: [&str; 16] = [
+    "black",
+    "red",
+    "green",
+    "yellow",
+    "blue",
+    "magenta",
+    "cyan",
+    "white",
+    "bright_black",
+    "bright_red",
+    "bright_green",
+    "bright_yellow",
+    "bright_blue",
+    "bright_magenta",
+    "bright_cyan",
+    "bright_white",
+];
+
+/// Usual values of the 16 basic colors, as in xterm
+const BASIC_COLORS: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// Levels of the red, green and blue components in the 6 * 6 * 6 cube of the 256 colors
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// A terminal color
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Color {
+    /// One of the 16 basic colors
+    Basic(u8),
+    /// One of the 256 colors
+    Indexed(u8),
+    /// A 24-bit color
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    /// Reads a basic color name, a number from 0 to 255 of at most 3 digits or a `#rrggbb`
+    /// hexadecimal color, the `#` being optional
+    pub fn from_name(name: &str) -> Option<Color> {
+        let name = name.trim_matches('"').to_lowercase();
+        if let Some(number) = BASIC_COLOR_NAMES.iter().position(|&basic| basic == name) {
+            return Some(Color::Basic(number as u8));
+        }
+        if name.len() <= 3 {
+            return name.parse().ok().map(Color::Indexed);
+        }
+        let hex = name.strip_prefix('#').unwrap_or(&name);
+        if hex.len() != 6 || !hex.chars().all(|digit| digit.is_ascii_hexdigit()) {
+            return None;
+        }
+        let component = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+        Some(Color::Rgb(component(0), component(2), component(4)))
+    }
+    pub fn name(&self) -> String {
+        match self {
+            Color::Basic(number) => BASIC_COLOR_NAMES[*number as usize].to_owned(),
+            Color::Indexed(number) => number.to_string(),
+            Color::Rgb(r, g, b) => format!("#{r:02x}{g:02x}{b:02x}"),
+        }
+    }
+    /// Returns the red, green and blue components of the color
+    fn rgb(&self) -> (u8, u8, u8) {
+        match *self {
+            Color::Basic(number) => BASIC_COLORS[number as usize],
+            Color::Indexed(number @ 0..16) => BASIC_COLORS[number as usize],
+            Color::Indexed(number @ 16..232) => {
+                let cube = number as usize - 16;
+                (
+                    CUBE_LEVELS[cube / 36],
+                    CUBE_LEVELS[cube / 6 % 6],
+                    CUBE_LEVELS[cube % 6],
+                )
+            }
+            Color::Indexed(number) => {
+                let gray = 8 + 10 * (number - 232);
+                (gray, gray, gray)
+            }
+            Color::Rgb(r, g, b) => (r, g, b),
+        }
+    }
+    /// Returns the closest of `colors`
+    fn closest(&self, colors: impl Iterator<Item = Color>) -> Color {
+        let (r, g, b) = self.rgb();
+        let distance = |color: &Color| {
+            let (r2, g2, b2) = color.rgb();
+            [(r, r2), (g, g2), (b, b2)]
+                .iter()
+                .map(|&(a, b)| (a as i32 - b as i32).pow(2))
+                .sum::<i32>()
+        };
+        colors.min_by_key(distance).unwrap()
+    }
+    /// Returns the escape sequence setting the foreground or background to the closest color
+    /// `support` allows, or nothing if colors aren't supported
+    pub fn escape_sequence(&self, support: ColorSupport, background: bool) -> String {
+        let color = match (support, *self) {
+            (ColorSupport::None, _) => return String::new(),
+            (ColorSupport::Basic, Color::Indexed(_) | Color::Rgb(..)) => {
+                self.closest((0..16).map(Color::Basic))
+            }
+            // The first 16 colors vary between terminals, so they are left out
+            (ColorSupport::Indexed, Color::Rgb(..)) => self.closest((16..=255).map(Color::Indexed)),
+            _ => *self,
+        };
+        let offset = if background { 10 } else { 0 };
+        match color {
+            Color::Basic(number @ 0..8) => format!("\x1b[{}m", 30 + offset + number),
+            Color::Basic(number) => format!("\x1b[{}m", 90 + offset + number - 8),
+            Color::Indexed(number) => format!("\x1b[{};5;{number}m", 38 + offset),
+            Color::Rgb(r, g, b) => format!("\x1b[{};2;{r};{g};{b}m", 38 + offset),
+        }
+    }
+}
+
+/// Lines drawn around the image
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FrameStyle {
+    Ascii,
+    Single,
+    Rounded,
+    Double,
+    Heavy,
+}
+
+impl FrameStyle {
+    pub fn from_name(name: &str) -> Option<FrameStyle> {
+        match name {
+            "ascii" => Some(FrameStyle::Ascii),
+            "single" => Some(FrameStyle::Single),
+            "rounded" => Some(FrameStyle::Rounded),
+            "double" => Some(FrameStyle::Double),
+            "heavy" => Some(FrameStyle::Heavy),
+            _ => None,
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            FrameStyle::Ascii => "ascii",
+            FrameStyle::Single => "single",
+            FrameStyle::Rounded => "rounded",
+            FrameStyle::Double => "double",
+            FrameStyle::Heavy => "heavy",
+        }
+    }
+    /// Returns the top-left, top-right, bottom-left and bottom-right corners, then the horizontal
+    /// and vertical lines
+    pub fn characters(&self) -> [char; 6] {
+        match self {
+            FrameStyle::Ascii => ['+', '+', '+', '+', '-', '|'],
+            FrameStyle::Single => ['┌', '┐', '└', '┘', '─', '│'],
+            FrameStyle::Rounded => ['╭', '╮', '╰', '╯', '─', '│'],
+            FrameStyle::Double => ['╔', '╗', '╚', '╝', '═', '║'],
+            FrameStyle::Heavy => ['┏', '┓', '┗', '┛', '━', '┃'],
+        }
+    }
+}
+
+/// How pixels and the frame look
+#[derive(Clone, PartialEq, Debug)]
+pub struct Theme {
+    /// Characters drawn twice for `t` and `f` pixels in the `blocks` render mode
+    pub on: char,
+    pub off: char,
+    /// Colors of `t` and `f` pixels
+    pub foreground: Option<Color>,
+    pub background: Option<Color>,
+    pub frame: FrameStyle,
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme {
+            on: '█',
+            off: ' ',
+            foreground: None,
+            background: None,
+            frame: FrameStyle::Ascii,
+        }
+    }
+}
+
+impl Theme {
+    pub fn from_preset(name: &str) -> Option<Theme> {
+        let black = Some(Color::Rgb(0, 0, 0));
+        match name {
+            "default" => Some(Theme::default()),
+            // Like monochrome displays
+            "amber" => Some(Theme {
+                foreground: Some(Color::Rgb(0xff, 0xb0, 0x00)),
+                background: black,
+                frame: FrameStyle::Single,
+                ..Theme::default()
+            }),
+            "phosphor" => Some(Theme {
+                foreground: Some(Color::Rgb(0x33, 0xff, 0x33)),
+                background: black,
+                frame: FrameStyle::Single,
+                ..Theme::default()
+            }),
+            "paper" => Some(Theme {
+                foreground: black,
+                background: Some(Color::Rgb(0xff, 0xff, 0xff)),
+                frame: FrameStyle::Rounded,
+                ..Theme::default()
+            }),
+            _ => None,
+        }
+    }
+    /// Returns the escape sequence setting the colors of the image, or nothing if it has none or
+    /// colors aren't supported
+    pub fn colors(&self, support: ColorSupport) -> String {
+        let mut sequence = String::new();
+        if let Some(foreground) = self.foreground {
+            sequence += &foreground.escape_sequence(support, false);
+        }
+        if let Some(background) = self.background {
+            sequence += &background.escape_sequence(support, true);
+        }
+        sequence
+    }
+    /// Returns the theme as it is set with `theme` commands
+    pub fn describe(&self) -> String {
+        let glyph = |glyph: char| {
+            if glyph == ' ' {
+                "space".to_owned()
+            } else {
+                glyph.to_string()
+            }
+        };
+        let color = |color: Option<Color>| color.map_or("none".to_owned(), |color| color.name());
+        format!(
+            "glyphs: {} {}\nforeground: {}\nbackground: {}\nframe: {}",
+            glyph(self.on),
+            glyph(self.off),
+            color(self.foreground),
+            color(self.background),
+            self.frame.name()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Color;
+
+    #[test]
+    fn color_names() {
+        assert_eq!(Color::from_name("bright_red"), Some(Color::Basic(9)));
+        assert_eq!(Color::from_name("7"), Some(Color::Indexed(7)));
+        assert_eq!(Color::from_name("080"), Some(Color::Indexed(80)));
+        assert_eq!(Color::from_name("255"), Some(Color::Indexed(255)));
+        assert_eq!(Color::from_name("256"), None);
+        assert_eq!(Color::from_name("000080"), Some(Color::Rgb(0, 0, 0x80)));
+        assert_eq!(
+            Color::from_name("\"#FFB000\""),
+            Some(Color::Rgb(0xff, 0xb0, 0))
+        );
+        assert_eq!(Color::from_name("0080"), None);
+        assert_eq!(Color::from_name("#12345g"), None);
+    }
+}
@@ -0,0 +1,269 @@
+//! Part of the image shown in the terminal, with zooming and scrolling
+
+use crate::{Cli, Image, Rect};
+use std::ops::Range;
+
+/// Lines of the terminal kept for the frame, the position line and the prompt
+const RESERVED_LINES: usize = 5;
+/// Largest size of the overview, in characters
+const OVERVIEW_COLUMNS: usize = 24;
+const OVERVIEW_ROWS: usize = 6;
+/// Zoom levels go from 1:2^MAX_ZOOM to 2^MAX_ZOOM:1
+pub const MAX_ZOOM: i32 = 4;
+
+/// The visible part of the image
+///
+/// Pixels are scaled to dots, which are then packed into characters by the render mode.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Viewport {
+    /// Coordinates of the top-left visible pixel
+    pub x: usize,
+    pub y: usize,
+    /// Pixels are shown as 2^zoom * 2^zoom dots, or 2^-zoom * 2^-zoom pixels share a dot
+    pub zoom: i32,
+}
+
+impl Viewport {
+    /// Number of dots showing `pixels` pixels
+    fn dots_showing(&self, pixels: usize) -> usize {
+        if self.zoom >= 0 {
+            pixels << self.zoom
+        } else {
+            pixels.div_ceil(1 << -self.zoom)
+        }
+    }
+    /// Number of whole pixels shown by `dots` dots, at least 1
+    fn pixels_shown_by(&self, dots: usize) -> usize {
+        if self.zoom >= 0 {
+            (dots >> self.zoom).max(1)
+        } else {
+            dots << -self.zoom
+        }
+    }
+    /// Returns the pixels shown by `dots` dots from `first_dot`, `origin` being the first pixel
+    pub fn pixel_range(&self, origin: usize, first_dot: usize, dots: usize) -> Range<usize> {
+        if self.zoom >= 0 {
+            origin + (first_dot >> self.zoom)..origin + ((first_dot + dots - 1) >> self.zoom) + 1
+        } else {
+            let size = 1 << -self.zoom;
+            origin + first_dot * size..origin + (first_dot + dots) * size
+        }
+    }
+    /// Returns the zoom as a ratio of dots to pixels
+    pub fn ratio(&self) -> String {
+        if self.zoom >= 0 {
+            format!("{}:1", 1 << self.zoom)
+        } else {
+            format!("1:{}", 1 << -self.zoom)
+        }
+    }
+}
+
+impl Cli<'_> {
+    /// Returns the number of dots fitting in the terminal, horizontally and vertically, leaving
+    /// room for the overview if the image doesn't fit
+    fn available_dots(&self) -> (usize, usize) {
+        let dots = self.dots_fitting(RESERVED_LINES);
+        if self.shows_whole_image(self.area_shown_by(dots)) {
+            dots
+        } else {
+            let (_, (_, overview_rows)) = self.overview_size();
+            self.dots_fitting(RESERVED_LINES + overview_rows + 2)
+        }
+    }
+    /// Returns the number of dots fitting in the terminal without `reserved_lines` lines
+    fn dots_fitting(&self, reserved_lines: usize) -> (usize, usize) {
+        let (columns, rows) = self.terminal_size;
+        let mode = self.render_options.mode;
+        let (cell_width, cell_height) = mode.cell_size();
+        // Keep room for the frame and the labels of the vertical ruler
+        let mut margin = 2;
+        if self.render_options.rulers {
+            margin += self.image.get_height().to_string().len();
+        }
+        let cells_across = (columns.saturating_sub(margin) / mode.cell_width()).max(1);
+        let cells_down = rows.saturating_sub(reserved_lines).max(1);
+        (cells_across * cell_width, cells_down * cell_height)
+    }
+    /// Returns the pixels inside the viewport
+    pub fn visible_area(&self) -> Rect {
+        self.area_shown_by(self.available_dots())
+    }
+    /// Returns the pixels inside the viewport when it is `dots` dots large
+    fn area_shown_by(&self, (dots_across, dots_down): (usize, usize)) -> Rect {
+        let x = self.viewport.x.min(self.image.get_width() - 1);
+        let y = self.viewport.y.min(self.image.get_height() - 1);
+        let w = (self.image.get_width() - x).min(self.viewport.pixels_shown_by(dots_across));
+        let h = (self.image.get_height() - y).min(self.viewport.pixels_shown_by(dots_down));
+        Rect {
+            x: x as i32,
+            y: y as i32,
+            w: w as i32,
+            h: h as i32,
+        }
+    }
+    /// Returns an image of the visible pixels at the current zoom
+    ///
+    /// When zoomed out, a dot is `true` if any of its pixels is.
+    fn visible_image(&self) -> Image {
+        let area = self.visible_area();
+        let width = self.viewport.dots_showing(area.w as usize);
+        let height = self.viewport.dots_showing(area.h as usize);
+        let mut image = Image::new(width as i32, height as i32, false);
+        for (dot_y, row) in image.grid.iter_mut().enumerate() {
+            for (dot_x, dot) in row.iter_mut().enumerate() {
+                *dot = if self.viewport.zoom >= 0 {
+                    let x = area.x as usize + (dot_x >> self.viewport.zoom);
+                    let y = area.y as usize + (dot_y >> self.viewport.zoom);
+                    self.image.grid[y][x]
+                } else {
+                    let size = 1 << -self.viewport.zoom;
+                    let start_x = area.x as usize + dot_x * size;
+                    let start_y = area.y as usize + dot_y * size;
+                    let end_x = (start_x + size).min((area.x + area.w) as usize);
+                    let end_y = (start_y + size).min((area.y + area.h) as usize);
+                    self.image.grid[start_y..end_y]
+                        .iter()
+                        .any(|line| line[start_x..end_x].contains(&true))
+                };
+            }
+        }
+        image
+    }
+    fn shows_whole_image(&self, area: Rect) -> bool {
+        area.x == 0
+            && area.y == 0
+            && area.w as usize == self.image.get_width()
+            && area.h as usize == self.image.get_height()
+    }
+    /// Returns the number of pixels across a character of the overview, which shows twice as
+    /// many down, then the number of characters across and down of the overview
+    fn overview_size(&self) -> (usize, (usize, usize)) {
+        let (width, height) = (self.image.get_width(), self.image.get_height());
+        // Characters are about twice as high as wide
+        let scale = width
+            .div_ceil(OVERVIEW_COLUMNS)
+            .max(height.div_ceil(2 * OVERVIEW_ROWS))
+            .max(1);
+        (scale, (width.div_ceil(scale), height.div_ceil(2 * scale)))
+    }
+    /// Returns a framed map of the whole image, where the visible part is darker
+    fn overview(&self, area: Rect) -> String {
+        let (scale, (columns, rows)) = self.overview_size();
+        let [top_left, top_right, bottom_left, bottom_right, horizontal, vertical] =
+            self.render_options.theme.frame.characters();
+        let line = horizontal.to_string().repeat(columns);
+        let mut overview = format!("{top_left}{line}{top_right}\n");
+        for row in 0..rows {
+            overview.push(vertical);
+            for column in 0..columns {
+                let cell = Rect {
+                    x: (column * scale) as i32,
+                    y: (row * 2 * scale) as i32,
+                    w: scale as i32,
+                    h: 2 * scale as i32,
+                };
+                let visible_part = cell.intersection(&area);
+                let is_visible = visible_part.w > 0 && visible_part.h > 0;
+                let end_x = (column + 1) * scale;
+                let end_y = ((row + 1) * 2 * scale).min(self.image.get_height());
+                let has_t_pixels = self.image.grid[row * 2 * scale..end_y]
+                    .iter()
+                    .any(|line| line[column * scale..end_x.min(line.len())].contains(&true));
+                overview.push(match (is_visible, has_t_pixels) {
+                    (true, true) => '█',
+                    (true, false) => '▒',
+                    (false, true) => '░',
+                    (false, false) => ' ',
+                });
+            }
+            overview.push(vertical);
+            overview.push('\n');
+        }
+        overview += &format!("{bottom_left}{line}{bottom_right}");
+        overview
+    }
+    /// Prints the visible part of the image, then an overview and the position of the visible
+    /// part if the image isn't entirely shown
+    pub fn print_image(&self) {
+        let area = self.visible_area();
+        let placement = Viewport {
+            x: area.x as usize,
+            y: area.y as usize,
+            zoom: self.viewport.zoom,
+        };
+        self.visible_image().print(&self.render_options, &placement);
+        let (width, height) = (self.image.get_width(), self.image.get_height());
+        if !self.shows_whole_image(area) {
+            println!("{}", self.overview(area));
+            println!(
+                "showing ({}, {}) to ({}, {}) of {width} * {height} at {}",
+                area.x,
+                area.y,
+                area.x + area.w - 1,
+                area.y + area.h - 1,
+                self.viewport.ratio()
+            );
+        } else if self.viewport.zoom != 0 {
+            println!("zoom: {}", self.viewport.ratio());
+        }
+    }
+    /// Sets the zoom level, keeping the centre of the viewport in place
+    pub fn set_zoom(&mut self, zoom: i32) {
+        let zoom = zoom.clamp(-MAX_ZOOM, MAX_ZOOM);
+        let area = self.visible_area();
+        let centre_x = area.x as usize + area.w as usize / 2;
+        let centre_y = area.y as usize + area.h as usize / 2;
+        self.viewport.zoom = zoom;
+        let area = self.visible_area();
+        self.viewport.x = centre_x.saturating_sub(area.w as usize / 2);
+        self.viewport.y = centre_y.saturating_sub(area.h as usize / 2);
+    }
+    /// Moves the top-left corner of the viewport, keeping it inside the image
+    pub fn pan(&mut self, x: i64, y: i64) {
+        self.viewport.x = x.clamp(0, self.image.get_width() as i64 - 1) as usize;
+        self.viewport.y = y.clamp(0, self.image.get_height() as i64 - 1) as usize;
+    }
+    /// Shows the whole image at the largest zoom that fits in the terminal
+    pub fn fit(&mut self) {
+        // The overview isn't shown when the image fits, whatever the current zoom; if no zoom
+        // fits, the smallest one is kept
+        let (dots_across, dots_down) = self.dots_fitting(RESERVED_LINES);
+        self.viewport = Viewport::default();
+        for zoom in (-MAX_ZOOM..=MAX_ZOOM).rev() {
+            self.viewport.zoom = zoom;
+            if self.viewport.dots_showing(self.image.get_width()) <= dots_across
+                && self.viewport.dots_showing(self.image.get_height()) <= dots_down
+            {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MAX_ZOOM;
+    use crate::render::RenderMode;
+    use crate::{Cli, Image};
+
+    #[test]
+    fn fit_doesnt_depend_on_the_zoom() {
+        for (width, height, expected_zoom) in [(60, 40, 0), (20, 10, 1), (300, 100, -2)] {
+            let mut image = Image::new(width, height, false);
+            let mut cli = Cli::new(String::new(), &mut image);
+            cli.render_options.mode = RenderMode::Braille;
+            cli.terminal_size = (40, 20);
+            for zoom in -MAX_ZOOM..=MAX_ZOOM {
+                cli.set_zoom(zoom);
+                cli.pan(5, 5);
+                cli.fit();
+                assert_eq!(
+                    cli.viewport.zoom, expected_zoom,
+                    "{width} * {height} from zoom {zoom}"
+                );
+                assert!(cli.shows_whole_image(cli.visible_area()));
+            }
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! Runs every script of `tests/fixtures` and compares the image with the PBM file of the same name,
+//! then checks that the scripts of `tests/fixtures/failing` fail
+//!
+//! Set `IPCLI_BLESS=1` to replace the PBM files with the current images.
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+#[test]
+fn fixtures_match_golden_images() {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let bless = std::env::var_os("IPCLI_BLESS").is_some_and(|value| value != "0");
+    let scripts = scripts_in(&fixtures);
+    let mut failures: Vec<String> = Vec::new();
+    for script in &scripts {
+        let name = script.file_stem().unwrap().to_string_lossy().into_owned();
+        let golden = script.with_extension("pbm");
+        let output = Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("{name}.pbm"));
+        let result = ipcli(&fixtures)
+            .arg("-o")
+            .arg(&output)
+            .arg("-s")
+            .arg(script)
+            .output()
+            .expect("failed to run ipcli");
+        if !result.status.success() {
+            failures.push(format!(
+                "{name}: the script failed\n{}",
+                String::from_utf8_lossy(&result.stderr)
+            ));
+            continue;
+        }
+        if bless {
+            std::fs::copy(&output, &golden).expect("failed to write the golden image");
+            continue;
+        }
+        let Ok(expected) = std::fs::read_to_string(&golden) else {
+            failures.push(format!("{name}: no golden image, run with IPCLI_BLESS=1"));
+            continue;
+        };
+        let actual = std::fs::read_to_string(&output).expect("failed to read the image");
+        if let Some(diff) = diff(&parse_pbm(&actual), &parse_pbm(&expected)) {
+            failures.push(format!("{name}: {diff}"));
+        }
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+/// Runs the failing scripts from another directory, checking the error given by their first line,
+/// `# error: [message]`
+#[test]
+fn failing_fixtures_fail() {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/failing");
+    let mut failures: Vec<String> = Vec::new();
+    for script in scripts_in(&fixtures) {
+        let name = script.file_stem().unwrap().to_string_lossy().into_owned();
+        let text = std::fs::read_to_string(&script).expect("failed to read the script");
+        let Some(expected) = text
+            .lines()
+            .next()
+            .and_then(|line| line.strip_prefix("# error: "))
+        else {
+            failures.push(format!("{name}: no `# error: [message]` line"));
+            continue;
+        };
+        let result = ipcli(Path::new(env!("CARGO_TARGET_TMPDIR")))
+            .arg("-s")
+            .arg(&script)
+            .output()
+            .expect("failed to run ipcli");
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        if result.status.code() != Some(1) {
+            failures.push(format!("{name}: expected to fail, not {}", result.status));
+        } else if !stderr.contains(&format!("script error: {expected}")) {
+            failures.push(format!(
+                "{name}: expected the error '{expected}', not\n{stderr}"
+            ));
+        }
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+/// Returns the scripts of a directory, sorted by name
+fn scripts_in(directory: &Path) -> Vec<PathBuf> {
+    let mut scripts: Vec<PathBuf> = std::fs::read_dir(directory)
+        .expect("failed to read the fixtures directory")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "ipcli")
+        })
+        .collect();
+    scripts.sort();
+    assert!(!scripts.is_empty(), "no fixtures found");
+    scripts
+}
+
+/// Returns a command running ipcli in batch mode from `directory`
+fn ipcli(directory: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_ipcli"));
+    command
+        .current_dir(directory)
+        // Keep the user's configuration out of the way
+        .env("XDG_CONFIG_HOME", env!("CARGO_TARGET_TMPDIR"))
+        .arg("-b")
+        .stdin(Stdio::null());
+    command
+}
+
+/// Reads a plain PBM (P1) file
+fn parse_pbm(text: &str) -> Vec<Vec<bool>> {
+    let mut tokens = text
+        .lines()
+        .map(|line| line.split('#').next().unwrap())
+        .flat_map(str::split_whitespace);
+    assert_eq!(tokens.next(), Some("P1"), "not a plain PBM file");
+    let width: usize = tokens.next().unwrap().parse().unwrap();
+    let height: usize = tokens.next().unwrap().parse().unwrap();
+    let bits: Vec<bool> = tokens.flat_map(str::chars).map(|bit| bit == '1').collect();
+    assert_eq!(bits.len(), width * height, "wrong number of pixels");
+    bits.chunks(width).map(<[bool]>::to_vec).collect()
+}
+
+/// Draws the images side by side with the differing pixels, or returns `None` if they are equal
+///
+/// `#` and `.` are matching `t` and `f` pixels, `+` and `-` are extra and missing `t` pixels.
+fn diff(actual: &[Vec<bool>], expected: &[Vec<bool>]) -> Option<String> {
+    let size = |grid: &[Vec<bool>]| (grid.first().map_or(0, Vec::len), grid.len());
+    if size(actual) != size(expected) {
+        return Some(format!(
+            "expected a {:?} image, not {:?}",
+            size(expected),
+            size(actual)
+        ));
+    }
+    if actual == expected {
+        return None;
+    }
+    let draw = |grid: &[Vec<bool>], y: usize| -> String {
+        grid[y]
+            .iter()
+            .map(|&pixel| if pixel { '#' } else { '.' })
+            .collect()
+    };
+    let mut text = String::from("the image differs (expected, actual, differences)\n");
+    for y in 0..actual.len() {
+        let differences: String = actual[y]
+            .iter()
+            .zip(&expected[y])
+            .map(|pixels| match pixels {
+                (true, true) => '#',
+                (false, false) => '.',
+                (true, false) => '+',
+                (false, true) => '-',
+            })
+            .collect();
+        text += &format!(
+            "{}   {}   {}\n",
+            draw(expected, y),
+            draw(actual, y),
+            differences
+        );
+    }
+    Some(text)
+}